@@ -0,0 +1,70 @@
+; Source of quirks_probe.ch8, a quirk probe for tests/quirks.rs. Each line is an address, the
+; instruction word stored there and its mnemonic. Addresses not listed hold zero. The five results
+; end up in VA to VE and are drawn left to right as font digits at the top of the screen.
+
+; Shift: 8xy6 shifts Vy into Vx with shift_uses_vy, else Vx in place. VA = 2 or 0
+0x200: 00E0  CLS
+0x202: 6101  LD V1, 0x01
+0x204: 6204  LD V2, 0x04
+0x206: 8126  SHR V1, V2
+0x208: 8A10  LD VA, V1
+
+; Load/store: with load_store_increments_i the first Fx65 moves I to 0x309, so the second reads
+; 1 instead of 0. VB = 1 or 0
+0x20A: A308  LD I, 0x308
+0x20C: F065  LD V0, [I]
+0x20E: F065  LD V0, [I]
+0x210: 8B00  LD VB, V0
+
+; Jump: Bnnn adds V3 (the top nibble of 0x300) with jump_uses_vx, else V0. The code at 0x300
+; sets VC = 1 or 0 and comes back to 0x218
+0x212: 6004  LD V0, 0x04
+0x214: 6300  LD V3, 0x00
+0x216: B300  JP V0, 0x300
+
+; Collision count: glyph 0 drawn twice at 0, 0 collides in all 5 rows. VD = 5 with
+; collision_row_count, else 1
+0x218: 6400  LD V4, 0x00
+0x21A: F429  LD F, V4
+0x21C: 6500  LD V5, 0x00
+0x21E: D555  DRW V5, V5, 0x5
+0x220: D555  DRW V5, V5, 0x5
+0x222: 8DF0  LD VD, VF
+0x224: 00E0  CLS
+
+; Wrap: glyph 0 at 62, 0 reaches columns 0 and 1 only with pixel_wrap, where glyph 0 at 0, 0
+; then collides. VE = 1 or 0
+0x226: 663E  LD V6, 0x3E
+0x228: D655  DRW V6, V5, 0x5
+0x22A: D555  DRW V5, V5, 0x5
+0x22C: 8EF0  LD VE, VF
+0x22E: 00E0  CLS
+
+; Results: VA to VE as digits 5 pixels apart
+0x230: 6700  LD V7, 0x00
+0x232: 6800  LD V8, 0x00
+0x234: FA29  LD F, VA
+0x236: D785  DRW V7, V8, 0x5
+0x238: 7705  ADD V7, 0x05
+0x23A: FB29  LD F, VB
+0x23C: D785  DRW V7, V8, 0x5
+0x23E: 7705  ADD V7, 0x05
+0x240: FC29  LD F, VC
+0x242: D785  DRW V7, V8, 0x5
+0x244: 7705  ADD V7, 0x05
+0x246: FD29  LD F, VD
+0x248: D785  DRW V7, V8, 0x5
+0x24A: 7705  ADD V7, 0x05
+0x24C: FE29  LD F, VE
+0x24E: D785  DRW V7, V8, 0x5
+0x250: 7705  ADD V7, 0x05
+0x252: 1252  JP 0x252
+
+; Jump targets, 0x300 with jump_uses_vx and 0x304 without
+0x300: 6C01  LD VC, 0x01
+0x302: 1218  JP 0x218
+0x304: 6C00  LD VC, 0x00
+0x306: 1218  JP 0x218
+
+; Data for the load/store probe
+0x308: 0001  DB 0x00, 0x01
@@ -0,0 +1,63 @@
+use crate::chip8::ADDR_PROGRAM_START;
+use crate::opcode::{self, Opcode};
+
+// Best-effort guess at which rom bytes are sprite data, following Annn loads into the Dxyn that draw them
+pub fn find_data(rom: &[u8]) -> Vec<bool> {
+    let mut data = vec![false; rom.len()];
+    let mut i = None;
+    for word in rom.chunks_exact(2) {
+        match opcode::decode(u16::from_be_bytes([word[0], word[1]])) {
+            Some(Opcode::LdIAddr { nnn }) => i = nnn.checked_sub(ADDR_PROGRAM_START).map(usize::from),
+            Some(Opcode::DrwVxVyNibble { n, .. }) => {
+                let Some(start) = i else { continue };
+                // Dxy0 draws a 16x16 sprite in high resolution
+                let len = if n == 0 { 32 } else { n as usize };
+                let end = (start + len).min(rom.len());
+                if start < end { data[start..end].fill(true); }
+            }
+            // I no longer points at a known address
+            Some(Opcode::AddIVx { .. } | Opcode::LdFVx { .. } | Opcode::LdHfVx { .. }) => i = None,
+            _ => {}
+        }
+    }
+    data
+}
+
+// Linear listing of the rom, bytes found by find_data are listed as .db
+pub fn disassemble(rom: &[u8]) -> Vec<String> {
+    let data = find_data(rom);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = ADDR_PROGRAM_START as usize + offset;
+        if data[offset] || offset + 1 == rom.len() || data[offset + 1] {
+            lines.push(format!("{:#05X}: {:02X}    .db {:#04X}", addr, rom[offset], rom[offset]));
+            offset += 1;
+        } else {
+            let op_code = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+            lines.push(format!("{:#05X}: {:04X}  {}", addr, op_code, opcode::mnemonic(op_code)));
+            offset += 2;
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        let rom = [0xA2, 0x06, 0xD0, 0x13, 0x12, 0x04, 0xF0, 0x90, 0xF0];
+
+        assert_eq!(find_data(&rom), [false, false, false, false, false, false, true, true, true]);
+        assert_eq!(disassemble(&rom), [
+            "0x200: A206  LD I, 0x206",
+            "0x202: D013  DRW V0, V1, 0x3",
+            "0x204: 1204  JP 0x204",
+            "0x206: F0    .db 0xF0",
+            "0x207: 90    .db 0x90",
+            "0x208: F0    .db 0xF0",
+        ]);
+    }
+}
@@ -0,0 +1,81 @@
+// Live playback of the sound timer tone through an SDL audio device
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    Sdl,
+};
+
+use crate::wav;
+
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+// Samples the tone fades in and out over when it starts or stops, about 1ms, so cutting
+// the square wave off mid period doesn't click
+const FADE_SAMPLES: f32 = 48.0;
+
+pub struct SquareWave {
+    pub frequency: f32,
+    pub volume: f32,
+    // Whether the tone should sound, the device keeps running either way
+    pub on: bool,
+    sample_rate: f32,
+    phase: f32,
+    gain: f32,
+}
+
+impl SquareWave {
+    pub fn new(sample_rate: i32) -> Self {
+        Self {
+            frequency: wav::TONE_FREQUENCY as f32,
+            volume: DEFAULT_VOLUME,
+            on: false,
+            sample_rate: sample_rate.max(1) as f32,
+            phase: 0.0,
+            gain: 0.0,
+        }
+    }
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let target = if self.on { 1.0 } else { 0.0 };
+        for sample in out.iter_mut() {
+            self.gain += (target - self.gain).clamp(-1.0 / FADE_SAMPLES, 1.0 / FADE_SAMPLES);
+            let level = if self.phase < 0.5 { self.volume } else { -self.volume };
+            *sample = level * self.gain;
+            self.phase = (self.phase + self.frequency / self.sample_rate) % 1.0;
+        }
+    }
+}
+
+// A running mono device playing silence until the wave is turned on
+pub fn open(sdl: &Sdl) -> Result<AudioDevice<SquareWave>, String> {
+    let desired = AudioSpecDesired { freq: Some(wav::SAMPLE_RATE as i32), channels: Some(1), samples: None };
+    let device = sdl.audio()?.open_playback(None, &desired, |spec| SquareWave::new(spec.freq))?;
+    device.resume();
+    Ok(device)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade() {
+        let mut wave = SquareWave::new(wav::SAMPLE_RATE as i32);
+        let mut out = [1.0; 256];
+        wave.callback(&mut out);
+        assert!(out.iter().all(|&s| s == 0.0));
+
+        wave.on = true;
+        wave.callback(&mut out);
+        assert!(out[0].abs() < DEFAULT_VOLUME / 10.0);
+        assert!(out[48..].iter().all(|&s| s.abs() == DEFAULT_VOLUME));
+
+        wave.on = false;
+        wave.callback(&mut out);
+        assert!(out[0].abs() > 0.0);
+        assert!(out[48..].iter().all(|&s| s == 0.0));
+    }
+}
@@ -1,9 +1,15 @@
-use std::fs::File;
-use std::io::Read;
-use std::time::Instant;
-
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use sdl2::{
-    event::Event,
+    audio::AudioDevice,
+    event::{Event, WindowEvent},
     EventPump,
     keyboard::Keycode,
     pixels::Color,
@@ -13,11 +19,28 @@ use sdl2::{
     video::Window,
 };
 
-use crate::keypad::Keypad;
-
-const ADDR_PROGRAM_START: u16 = 0x200;
-
-const FONT_SET: [u8; 80] = [
+use crate::audio::{self, SquareWave};
+use crate::delta::{self, FrameChange};
+use crate::gif::Recorder;
+use crate::hud;
+use crate::opcode::{self, Opcode};
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::png;
+use crate::quirks::Quirks;
+use crate::timing::{self, FixedTimestep, MAX_CATCH_UP_FRAMES, TIMER_HZ};
+use crate::trace::TraceWriter;
+use crate::watcher::RomWatcher;
+#[cfg(feature = "record-audio")]
+use crate::wav;
+use crate::keypad::{KeyMap, Keypad};
+use crate::metrics::Metrics;
+
+pub(crate) const ADDR_PROGRAM_START: u16 = 0x200;
+const ADDR_LAST_INSTRUCTION: u16 = 0xFFE;
+const MAX_ROM_SIZE: usize = 4096 - ADDR_PROGRAM_START as usize;
+
+pub(crate) const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -36,17 +59,176 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+const GLYPH_SIZE: usize = 5;
+
+// Drawn for Fx29 digits a short custom font lacks
+const MISSING_GLYPH: [u8; GLYPH_SIZE] = [0xF0; GLYPH_SIZE];
+
+// SCHIP 8x10 digits for Fx30, stored right after the small font when loaded
+const BIG_GLYPH_SIZE: usize = 10;
+const MAX_BIG_GLYPHS: usize = 10;
+const ADDR_BIG_FONT: usize = FONT_SET.len();
+
 const FRAME_WIDTH: usize = 64;
 const FRAME_HEIGHT: usize = 32;
+const MIN_FRAME_SIZE: usize = 8;
+const MAX_FRAME_WIDTH: usize = 256;
+const MAX_FRAME_HEIGHT: usize = 128;
+
+// Fx55 writes to this address are passed to the semihosting handler
+pub const SEMIHOSTING_ADDR: u16 = 0xFFF;
+
+const DEFAULT_SCALE: u32 = 10;
+pub const MIN_SCALE: u32 = 1;
+pub const MAX_SCALE: u32 = 40;
 
-const SCALE: u32 = 10;
+const CYCLES_PER_FRAME: u32 = 10;
+
+const FX0A_COOLDOWN_TICKS: u8 = 4;
+
+// Ten seconds of frames
+const REWIND_FRAMES: usize = 600;
+
+// Intensity a cleared pixel loses each frame when fading, gone after four frames
+const FADE_STEP: u8 = 64;
+
+// Save states start with the magic and a format version byte
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 2;
 
 pub const WINDOW_TITLE: &str = "CHIP-8 interpreter";
-const WINDOW_WIDTH: u32 = (FRAME_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (FRAME_HEIGHT as u32) * SCALE;
 
 const RGB_BLACK: (u8, u8, u8) = (0, 0, 0);
 const RGB_WHITE: (u8, u8, u8) = (255, 255, 255);
+const RGB_HUD: (u8, u8, u8) = (255, 64, 64);
+const RGB_RULER: (u8, u8, u8) = (96, 96, 96);
+
+const RULER_SPACING: usize = 8;
+
+const RGB_KEY_UP: (u8, u8, u8) = (40, 40, 40);
+const RGB_KEY_DOWN: (u8, u8, u8) = (200, 200, 200);
+
+const DEBUG_PANEL_WIDTH: u32 = 128;
+const DEBUG_PANEL_TEXT_SCALE: u32 = 2;
+const DEBUG_PANEL_LINE_HEIGHT: u32 = 12;
+
+const KEYPAD_PANEL_HEIGHT: u32 = 64;
+const KEY_CELL_WIDTH: u32 = 16;
+const KEY_CELL_HEIGHT: u32 = 14;
+
+// Hex keypad as laid out on the COSMAC VIP
+const KEYPAD_LAYOUT: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ByteSwap {
+    Never,
+    Always,
+    // Swaps when more of the ROM decodes to known opcodes that way
+    Auto,
+}
+
+// Snapshot of the registers, for debugging views
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MachineState {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    StackOverflow,
+    StackUnderflow,
+    // The executor itself panicked, only raised in safe mode
+    InternalPanic,
+    // pc landed on an odd address, only raised in strict alignment mode
+    MisalignedPc(u16),
+    // A watched register changed to the watched value
+    RegisterWatch { reg: u8, value: u8 },
+    // A sprite draw collided while breaking on collision was armed
+    Collision,
+    // 00EE popped a call site whose next instruction is outside the program area
+    InvalidReturn(u16),
+}
+
+#[derive(Debug)]
+pub enum Chip8Error {
+    Io(io::Error),
+    // The rom does not fit in memory after 0x200
+    RomTooLarge { size: usize },
+    // The data passed to load_state isn't a save state this version can read
+    InvalidState,
+    // The machine halted, see Fault for the causes
+    Fault { fault: Fault, pc: u16 },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{}", error),
+            Self::RomTooLarge { size } => write!(f, "rom is {} bytes, at most {} fit in memory", size, MAX_ROM_SIZE),
+            Self::InvalidState => write!(f, "not a save state, or one from another version"),
+            Self::Fault { fault, pc } => write!(f, "{:?} at {:#05X}", fault, pc),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+impl From<io::Error> for Chip8Error {
+    fn from(error: io::Error) -> Self { Self::Io(error) }
+}
+
+// Reads a save state front to back, running out of data is an invalid state
+struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        if self.data.len() < len { return Err(Chip8Error::InvalidState); }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, Chip8Error> { Ok(self.take(1)?[0]) }
+
+    fn word(&mut self) -> Result<u16, Chip8Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+// Per-address access counters
+struct Profile {
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+    executes: Vec<u32>,
+}
+
+// Machine state at the start of a frame, for rewinding
+struct Snapshot {
+    v: [u8; 16],
+    i: u16,
+    stack: [u16; 32],
+    sp: u8,
+    dt: u8,
+    st: u8,
+    frame: Vec<Vec<u8>>,
+    hires: bool,
+    pc: u16,
+    memory: [u8; 4096],
+    frame_count: u64,
+}
 
 pub struct Chip8 {
     // V0 - VF
@@ -68,7 +250,11 @@ pub struct Chip8 {
     st: u8,
 
     // Display
-    frame: [[u8; FRAME_WIDTH]; FRAME_HEIGHT],
+    frame: Vec<Vec<u8>>,
+    width: usize,
+    height: usize,
+    // SCHIP high resolution, twice the width and height
+    hires: bool,
 
     // Program counter
     pc: u16,
@@ -76,25 +262,181 @@ pub struct Chip8 {
     // Memory
     memory: [u8; 4096],
 
-    // Keypad
+    // Keypad, and the key that quits instead of reaching it
     keypad: Keypad,
+    quit_key: Keycode,
+
+    // Window canvas and event pump, None when headless
+    canvas: Option<Canvas<Window>>,
+    event_pump: Option<EventPump>,
+
+    // Number of emulated frames
+    frame_count: u64,
+
+    // Number of executed instructions and wall time spent in run_unthrottled
+    instruction_count: u64,
+    busy_time: Duration,
+
+    // The rom as loaded, after any byte swap, unaffected by the program writing over it
+    rom: Vec<u8>,
+
+    // Highest memory address written by the program
+    max_written_addr: u16,
+
+    // Pixels erased by the last sprite draw
+    last_collision_count: u32,
+
+    // Screen pixels per display pixel
+    scale: u32,
+
+    // Colors the display is drawn in
+    palette: Palette,
+
+    // Debug overlays
+    debug_hud: bool,
+    debug_panel: bool,
+    ruler: bool,
+    show_keys: bool,
+
+    // Set whenever the window content may differ from the last present, so idle cycles skip drawing
+    screen_dirty: bool,
+    // Display pixels filled in, one rect each, over all redraws
+    fill_rects: u64,
+
+    // Byte order recovery for byte-swapped ROM dumps
+    byte_swap: ByteSwap,
 
-    // Canvas
-    canvas: Canvas<Window>,
+    // Holds execution until a key is pressed
+    waiting_for_key: bool,
 
-    // Event Pump
-    event_pump: EventPump,
+    // Machine being emulated and the implementation specific behaviors, which start as its
+    // quirks but can be changed on their own
+    platform: Platform,
+    quirks: Quirks,
+
+    // Memory heatmap, off by default
+    profile: Option<Profile>,
+
+    // Snapshots of the last REWIND_FRAMES frames, newest last
+    rewind: Option<VecDeque<Snapshot>>,
+    frame_delta: Option<Vec<FrameChange>>,
+
+    // Whether the tone sounded in each frame, when recording audio
+    sound_timeline: Option<Vec<bool>>,
+
+    // Intensity of each pixel when fading, full while lit and decaying once cleared
+    fade: Option<Vec<Vec<u8>>>,
+
+    // GIF being recorded and the path it's saved to
+    recording: Option<(String, Recorder)>,
+
+    // Plays the tone live, None when no audio device could be opened
+    audio: Option<AudioDevice<SquareWave>>,
+
+    // Silences the tone, and the lowest sound timer value that sounds it
+    muted: bool,
+    min_sound_timer: u8,
+
+    // Halts execution and timers
+    paused: bool,
+
+    // Binary instruction trace, and the write error that ended it early
+    trace: Option<TraceWriter>,
+    trace_error: Option<io::Error>,
+
+    // Set when the program does something the machine can't, halts execution
+    fault: Option<Fault>,
+
+    // Turns panics while executing into Fault::InternalPanic
+    safe_mode: bool,
+
+    // Faults on an odd pc instead of fetching from it
+    strict_alignment: bool,
+
+    // Ticks the timers after every instruction instead of every frame, how some roms were tuned
+    legacy_timer_mode: bool,
+
+    // (register, value) pairs that halt execution when the register changes to the value
+    register_watches: Vec<(u8, u8)>,
+
+    // Halts on the next sprite collision, then disarms
+    break_on_collision: bool,
+
+    // Reloads the rom when its file changes
+    watcher: Option<RomWatcher>,
+
+    // Periodic screenshots
+    screenshot_interval: Option<Duration>,
+    screenshot_count: u32,
+
+    // Address and word of every unknown opcode hit, when enabled
+    invalid_log: Option<Vec<(u16, u16)>>,
+
+    // Cap on frames run at once after a stall
+    max_catch_up_frames: u32,
+
+    // Runs instructions at this rate instead of CYCLES_PER_FRAME a frame
+    target_ips: Option<u32>,
+
+    // Rate of the frames that tick dt and st, independent of the instruction rate
+    timer_hz: u32,
+
+    // Timer ticks during which Fx0A ignores the key it last latched
+    fx0a_cooldown: u8,
+    fx0a_latch: Option<(u8, u8)>,
+    // Whether Fx0A has started waiting for a key to be pressed and released
+    fx0a_waiting: bool,
+    // Whether a draw under the display_wait quirk holds the CPU until the next frame
+    display_waiting: bool,
+
+    // Byte registers and unused memory hold at power on
+    power_on_fill: u8,
+
+    // Glyphs in the loaded font, Fx29 digits past them clamp to the last or, with
+    // missing_glyph_box, point at MISSING_GLYPH stored right after the font
+    font_glyphs: u8,
+    missing_glyph_box: bool,
+    // Glyphs in the big font, none until one is loaded, in which case Fx30 falls back to the small font
+    big_font_glyphs: u8,
+
+    // Source of Cxkk, seedable for reproducible runs. A fresh machine picks a random seed,
+    // kept so a session can be replayed with seed_rng
+    rng: StdRng,
+    seed: u64,
+
+    // Lets test roms report results to the host through Fx55
+    semihosting: Option<Box<dyn FnMut(u8)>>,
+
+    // Called with dt and st after every timer tick, e.g. to animate timer gauges
+    on_timer_tick: Option<Box<dyn FnMut(u8, u8)>>,
 }
 
 impl Chip8 {
-    pub fn new(sdl: &Sdl) -> Self {
-        let mut memory = [0; 4096];
-        memory[..80].copy_from_slice(&FONT_SET);
+    pub fn new(sdl: &Sdl) -> Self { Self::with_display(sdl, FRAME_WIDTH, FRAME_HEIGHT) }
 
+    // Machine with a width x height display, for experimental variants
+    pub fn with_display(sdl: &Sdl, width: usize, height: usize) -> Self {
+        let mut chip = Self::headless_with_display(width, height);
         let video_subsystem = sdl.video().expect("Could not create Video Subsystem!");
-        let window_builder = video_subsystem.window(WINDOW_TITLE, WINDOW_WIDTH, WINDOW_HEIGHT)
+        let (window_width, window_height) = chip.window_size();
+        let window_builder = video_subsystem.window(WINDOW_TITLE, window_width, window_height)
             .build().expect("Could not create Window!");
-        let canvas = window_builder.into_canvas().build().expect("Could not create Canvas!");
+        chip.canvas = Some(window_builder.into_canvas().present_vsync().build().expect("Could not create Canvas!"));
+        chip.event_pump = Some(sdl.event_pump().expect("Event Issue"));
+        chip.audio = audio::open(sdl).ok();
+        chip
+    }
+
+    // Machine without a window, events or audio, for tests and non-graphical hosts. It runs
+    // through step and step_frame, start_cycle never sees a quit without an event pump
+    pub fn new_headless() -> Self { Self::headless_with_display(FRAME_WIDTH, FRAME_HEIGHT) }
+
+    pub fn headless_with_display(width: usize, height: usize) -> Self {
+        assert!((MIN_FRAME_SIZE..=MAX_FRAME_WIDTH).contains(&width), "Display width out of range!");
+        assert!((MIN_FRAME_SIZE..=MAX_FRAME_HEIGHT).contains(&height), "Display height out of range!");
+        let mut memory = [0; 4096];
+        memory[..80].copy_from_slice(&FONT_SET);
+        let seed = rand::random();
 
         Self {
             v: [0; 16],
@@ -103,807 +445,3477 @@ impl Chip8 {
             sp: 0,
             dt: 0,
             st: 0,
-            frame: [[0; FRAME_WIDTH]; FRAME_HEIGHT],
+            frame: vec![vec![0; width]; height],
+            width,
+            height,
+            hires: false,
             pc: ADDR_PROGRAM_START,
             memory,
             keypad: Keypad::new(),
-            canvas,
-            event_pump: sdl.event_pump().expect("Event Issue"),
+            quit_key: Keycode::Escape,
+            canvas: None,
+            event_pump: None,
+            frame_count: 0,
+            instruction_count: 0,
+            busy_time: Duration::ZERO,
+            rom: Vec::new(),
+            max_written_addr: 0,
+            last_collision_count: 0,
+            scale: DEFAULT_SCALE,
+            palette: Palette::default(),
+            debug_hud: false,
+            debug_panel: false,
+            ruler: false,
+            show_keys: false,
+            screen_dirty: true,
+            fill_rects: 0,
+            byte_swap: ByteSwap::Never,
+            waiting_for_key: false,
+            platform: Platform::Chip8,
+            quirks: Quirks::default(),
+            profile: None,
+            rewind: None,
+            frame_delta: None,
+            sound_timeline: None,
+            recording: None,
+            fade: None,
+            audio: None,
+            muted: false,
+            min_sound_timer: 1,
+            paused: false,
+            trace: None,
+            trace_error: None,
+            fault: None,
+            safe_mode: false,
+            strict_alignment: false,
+            legacy_timer_mode: false,
+            register_watches: Vec::new(),
+            break_on_collision: false,
+            watcher: None,
+            screenshot_interval: None,
+            screenshot_count: 0,
+            invalid_log: None,
+            max_catch_up_frames: MAX_CATCH_UP_FRAMES,
+            target_ips: None,
+            timer_hz: TIMER_HZ,
+            fx0a_cooldown: FX0A_COOLDOWN_TICKS,
+            fx0a_latch: None,
+            fx0a_waiting: false,
+            display_waiting: false,
+            power_on_fill: 0,
+            font_glyphs: (FONT_SET.len() / GLYPH_SIZE) as u8,
+            missing_glyph_box: false,
+            big_font_glyphs: 0,
+            rng: StdRng::seed_from_u64(seed),
+            seed,
+            semihosting: None,
+            on_timer_tick: None,
         }
     }
 
-    pub fn load_rom(&mut self, path: &str) {
-        let file = File::open(path).expect("Could not read rom!");
-        for (i, byte) in file.bytes().enumerate() {
-            let addr = ADDR_PROGRAM_START as usize + i;
-            self.memory[addr] = byte.expect("Could not read rom!");
+    pub fn load_rom(&mut self, path: &str) -> Result<(), Chip8Error> {
+        self.load_rom_bytes(&fs::read(path)?)
+    }
+
+    // Replaces whatever was loaded before, so nothing of a longer previous rom is left behind
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        if rom.len() > MAX_ROM_SIZE { return Err(Chip8Error::RomTooLarge { size: rom.len() }); }
+        let start = ADDR_PROGRAM_START as usize;
+        self.memory[start..].fill(self.power_on_fill);
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.pc = ADDR_PROGRAM_START;
+        self.i = ADDR_PROGRAM_START;
+        if self.should_swap_bytes(rom) {
+            for word in self.memory[start..start + rom.len()].chunks_exact_mut(2) { word.swap(0, 1); }
+        }
+        self.rom = self.memory[start..start + rom.len()].to_vec();
+        Ok(())
+    }
+
+    // Registers, memory and display, big-endian. Settings and the SDL handles aren't saved
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.memory.len() + self.width * self.height + 128);
+        data.extend_from_slice(STATE_MAGIC);
+        data.push(STATE_VERSION);
+        data.extend_from_slice(&self.v);
+        data.extend_from_slice(&self.i.to_be_bytes());
+        for addr in self.stack { data.extend_from_slice(&addr.to_be_bytes()); }
+        data.extend_from_slice(&[self.sp, self.dt, self.st]);
+        data.extend_from_slice(&self.pc.to_be_bytes());
+        data.extend_from_slice(&self.memory);
+        // The rom as loaded, which reset restores
+        data.extend_from_slice(&(self.rom.len() as u16).to_be_bytes());
+        data.extend_from_slice(&self.rom);
+        data.push(self.hires as u8);
+        data.extend_from_slice(&(self.width as u16).to_be_bytes());
+        data.extend_from_slice(&(self.height as u16).to_be_bytes());
+        for row in &self.frame { data.extend_from_slice(row); }
+        data
+    }
+
+    // Leaves the machine untouched when the state can't be read
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader = StateReader { data };
+        if reader.take(STATE_MAGIC.len())? != STATE_MAGIC || reader.byte()? != STATE_VERSION {
+            return Err(Chip8Error::InvalidState);
+        }
+        let v: [u8; 16] = reader.take(16)?.try_into().expect("Could not read registers!");
+        let i = reader.word()?;
+        let mut stack = [0; 32];
+        for addr in &mut stack { *addr = reader.word()?; }
+        let (sp, dt, st) = (reader.byte()?, reader.byte()?, reader.byte()?);
+        let pc = reader.word()?;
+        let memory: [u8; 4096] = reader.take(4096)?.try_into().expect("Could not read memory!");
+        let rom_len = reader.word()? as usize;
+        if rom_len > MAX_ROM_SIZE { return Err(Chip8Error::InvalidState); }
+        let rom = reader.take(rom_len)?.to_vec();
+        let hires = reader.byte()? != 0;
+        let (width, height) = (reader.word()? as usize, reader.word()? as usize);
+        // The display has to be this machine's, at twice the size in high resolution
+        let scale = |low: usize| if hires { low * 2 } else { low };
+        let (low_width, low_height) = if self.hires { (self.width / 2, self.height / 2) } else { (self.width, self.height) };
+        if sp as usize > stack.len()
+            || !(MIN_FRAME_SIZE..=MAX_FRAME_WIDTH).contains(&width)
+            || !(MIN_FRAME_SIZE..=MAX_FRAME_HEIGHT).contains(&height)
+            || (width, height) != (scale(low_width), scale(low_height))
+        {
+            return Err(Chip8Error::InvalidState);
+        }
+        let frame: Vec<Vec<u8>> = reader.take(width * height)?.chunks(width).map(<[u8]>::to_vec).collect();
+        if !reader.data.is_empty() { return Err(Chip8Error::InvalidState); }
+
+        (self.v, self.i, self.stack, self.sp, self.dt, self.st, self.pc) = (v, i, stack, sp, dt, st, pc);
+        (self.memory, self.rom) = (memory, rom);
+        (self.hires, self.width, self.height) = (hires, width, height);
+        self.frame = frame;
+        if let Some(changes) = &mut self.frame_delta { *changes = delta::full_frame(&self.frame); }
+        self.resize_window();
+        self.fx0a_waiting = false;
+        self.display_waiting = false;
+        self.fault = None;
+        Ok(())
+    }
+
+    pub fn rom(&self) -> &[u8] { &self.rom }
+
+    // Stable identifier of the loaded rom, usable as a key for per-rom settings
+    pub fn rom_hash(&self) -> u64 { fnv1a(self.rom()) }
+
+    // Address of every word in the rom and whether it decodes to a known instruction
+    pub fn validate_rom(&self) -> Vec<(u16, bool)> {
+        self.rom.chunks(2)
+            .enumerate()
+            .map(|(j, word)| {
+                let op_code = u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or(0)]);
+                (ADDR_PROGRAM_START + j as u16 * 2, word.len() == 2 && opcode::decode(op_code).is_some())
+            })
+            .collect()
+    }
+
+    // Address and decode of every rom word that is a known instruction, unknown words are skipped
+    pub fn instructions(&self) -> impl Iterator<Item = (u16, Opcode)> + '_ {
+        self.rom().chunks_exact(2)
+            .enumerate()
+            .filter_map(|(j, word)| {
+                let op_code = opcode::decode(u16::from_be_bytes([word[0], word[1]]))?;
+                Some((ADDR_PROGRAM_START + j as u16 * 2, op_code))
+            })
+    }
+
+    pub fn set_byte_swap(&mut self, mode: ByteSwap) { self.byte_swap = mode; }
+
+    pub fn set_wait_for_key(&mut self, enabled: bool) { self.waiting_for_key = enabled; }
+
+    pub fn is_paused(&self) -> bool { self.paused }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.update_tone();
+        let title = if paused { format!("{} (paused)", WINDOW_TITLE) } else { String::from(WINDOW_TITLE) };
+        if let Some(canvas) = &mut self.canvas {
+            canvas.window_mut().set_title(&title).expect("Could not set window title!");
         }
     }
 
-    pub fn start_cycle(&mut self) {
-        let mut start = Instant::now();
+    pub fn enable_invalid_log(&mut self) { self.invalid_log = Some(Vec::new()); }
 
-        'cycle: loop {
-            if self.handle_quit() { break 'cycle; }
+    pub fn invalid_op_codes(&self) -> &[(u16, u16)] { self.invalid_log.as_deref().unwrap_or_default() }
 
-            let keys: Vec<Keycode> = self.get_pressed_keys();
-            if keys.contains(&Keycode::Escape) { break 'cycle; }
-            self.keypad.down_keys(keys);
-            
-            if self.should_delay_cycle(&mut start) { continue; }
+    pub fn set_max_catch_up_frames(&mut self, frames: u32) { self.max_catch_up_frames = frames; }
 
-            self.decrement_timers();
+    pub fn set_target_ips(&mut self, ips: u32) { self.target_ips = Some(ips.max(1)); }
 
-            let op_code = self.fetch_next_op_code();
-            self.run_op_code(op_code);
+    pub fn set_timer_hz(&mut self, hz: u32) { self.timer_hz = hz.max(1); }
+
+    pub fn set_fx0a_cooldown(&mut self, ticks: u8) { self.fx0a_cooldown = ticks; }
+
+    pub fn quirks(&self) -> Quirks { self.quirks }
+
+    pub fn platform(&self) -> Platform { self.platform }
+
+    // Applies the platform's quirks and display size and resets, keeping the loaded rom so the
+    // same rom can be compared across platforms. Memory stays 4K, XO-CHIP's 64K isn't emulated
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.quirks = platform.quirks();
+        self.hires = false;
+        (self.width, self.height) = platform.display_size();
+        // Snapshots hold frames of the old size
+        if let Some(rewind) = &mut self.rewind { rewind.clear(); }
+        self.reset();
+        self.resize_window();
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) { self.quirks = quirks; }
 
-            self.keypad.clear_keys();
-            self.update_screen();
+    fn should_swap_bytes(&self, rom: &[u8]) -> bool {
+        match self.byte_swap {
+            ByteSwap::Never => false,
+            ByteSwap::Always => true,
+            ByteSwap::Auto => {
+                let known = |swap: bool| rom.chunks_exact(2)
+                    .map(|w| if swap { [w[1], w[0]] } else { [w[0], w[1]] })
+                    .filter(|w| opcode::decode(u16::from_be_bytes(*w)).is_some())
+                    .count();
+                known(true) > known(false)
+            }
         }
     }
 
-    fn handle_quit(&mut self) -> bool {
-        for event in self.event_pump.poll_iter() {
-            if let Event::Quit { .. } = event { return true; }
+    // Writes the display as a PNG, scaled like the window
+    pub fn screenshot(&self, path: &str) -> Result<(), Chip8Error> {
+        let (width, height) = self.display_size();
+        let scale = self.scale as usize;
+        let mut rgb = Vec::with_capacity((width * height * 3) as usize);
+        for y in 0..height as usize {
+            for x in 0..width as usize {
+                let (r, g, b) = self.palette.color(self.frame[y / scale][x / scale]);
+                rgb.extend_from_slice(&[r, g, b]);
+            }
         }
-        false
+        fs::write(path, png::encode(width, height, &rgb))?;
+        Ok(())
     }
 
-    fn get_pressed_keys(&self) -> Vec<Keycode> {
-        self.event_pump.keyboard_state()
-            .pressed_scancodes().filter_map(Keycode::from_scancode).collect()
+    // Captures the display every frame until stop_recording, replacing any recording in progress
+    pub fn start_recording(&mut self, path: &str) {
+        let recorder = Recorder::new(self.width, self.height, self.scale as usize, &self.palette);
+        self.recording = Some((path.to_string(), recorder));
     }
 
-    fn should_delay_cycle(&self, start: &mut Instant) -> bool {
-        if start.elapsed().as_millis() <= self.dt as u128 { return true; }
-        *start = Instant::now();
-        false
+    pub fn is_recording(&self) -> bool { self.recording.is_some() }
+
+    // Saves the recording, doing nothing when none is in progress
+    pub fn stop_recording(&mut self) -> Result<(), Chip8Error> {
+        let Some((path, recorder)) = self.recording.take() else { return Ok(()) };
+        fs::write(path, recorder.finish())?;
+        Ok(())
     }
 
-    fn fetch_next_op_code(&self) -> u16 {
-        let pc = self.pc as usize;
-        ((self.memory[pc] as u16) << 8) | self.memory[pc+1] as u16   
+    pub fn set_screenshot_interval(&mut self, interval: Duration) { self.screenshot_interval = Some(interval); }
+
+    pub fn watch_rom(&mut self, path: &str) { self.watcher = Some(RomWatcher::new(path)); }
+
+    pub fn set_semihosting(&mut self, handler: impl FnMut(u8) + 'static) { self.semihosting = Some(Box::new(handler)); }
+
+    pub fn set_on_timer_tick(&mut self, handler: impl FnMut(u8, u8) + 'static) { self.on_timer_tick = Some(Box::new(handler)); }
+
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.seed = seed;
     }
 
-    fn decrement_timers(&mut self) {
-        if self.dt > 0 { self.dt -= 1; }
-        if self.st > 0 { self.st -= 1; }
+    pub fn current_seed(&self) -> u64 { self.seed }
+
+    // Display rows, one byte per pixel
+    pub fn frame(&self) -> &[Vec<u8>] { &self.frame }
+
+    // Blanks the display only, unlike 00E0 pc and the rest of the machine are left alone
+    pub fn clear_display(&mut self) {
+        self.frame = vec![vec![0; self.width]; self.height];
+        self.screen_dirty = true;
+        self.record_clear();
     }
 
-    fn update_screen(&mut self) {
-        for y in 0..FRAME_HEIGHT {
-            for x in 0..FRAME_WIDTH {
-                let rgb = if self.frame[y][x] == 1 { RGB_WHITE } else { RGB_BLACK };
-                let color = Color::from(rgb);
+    pub fn hires(&self) -> bool { self.hires }
 
-                self.canvas.set_draw_color(color);
-                self.canvas.fill_rect(Rect::new(
-                    (x as u32 * SCALE) as i32,
-                    (y as u32 * SCALE) as i32,
-                    SCALE,
-                    SCALE,
-                )).expect("Fill Rect Issue");
-            }
+    // Switches between the display size and twice it, both clear the display as on SCHIP
+    fn set_hires(&mut self, hires: bool) {
+        if hires != self.hires {
+            self.hires = hires;
+            (self.width, self.height) = if hires {
+                (self.width * 2, self.height * 2)
+            } else {
+                (self.width / 2, self.height / 2)
+            };
+            self.resize_window();
         }
-        self.canvas.present();
+        self.clear_display();
     }
 
-    fn run_op_code(&mut self, code: u16) {
-        let (op1, op2, op3, op4) = (
-            ((code & 0xF000) >> 12) as u8,
-            ((code & 0x0F00) >> 8) as u8,
-            ((code & 0x00F0) >> 4) as u8,
-            (code & 0x000F) as u8,
-        );
-
-        // A 12-bit value, the lowest 12 bits of the instruction
-        let nnn: u16 = code & 0xFFF;
-
-        // A 4-bit value, the lowest 4 bits of the instruction
-        let n: u8 = op4;
-
-        // A 4-bit value, the lower 4 bits of the high byte of the instruction
-        let x: u8 = op2;
-
-        // A 4-bit value, the upper 4 bits of the low byte of the instruction
-        let y: u8 = op3;
-
-        // An 8-bit value, the lowest 8 bits of the instruction
-        let kk: u8 = (code & 0xFF) as u8;
-
-        match (op1, op2, op3, op4) {
-            (0x0, 0x0, 0xE, 0xE) => self.ret(),
-            (0x0, 0x0, 0xE, 0x0) => self.cls(),
-            (0x1, _, _, _) => self.jp_addr(nnn),
-            (0x2, _, _, _) => self.call_addr(nnn),
-            (0x3, _, _, _) => self.se_vx_byte(x, kk),
-            (0x4, _, _, _) => self.sne_vx_byte(x, kk),
-            (0x5, _, _, 0x0) => self.se_vx_vy(x, y),
-            (0x6, _, _, _) => self.ld_vx_byte(x, kk),
-            (0x7, _, _, _) => self.add_vx_byte(x, kk),
-            (0x8, _, _, 0x0) => self.ld_vx_vy(x, y),
-            (0x8, _, _, 0x1) => self.or_vx_vy(x, y),
-            (0x8, _, _, 0x2) => self.and_vx_vy(x, y),
-            (0x8, _, _, 0x3) => self.xor_vx_vy(x, y),
-            (0x8, _, _, 0x4) => self.add_vx_vy(x, y),
-            (0x8, _, _, 0x5) => self.sub_vx_vy(x, y),
-            (0x8, _, _, 0x6) => self.shr_vx_vy(x),
-            (0x8, _, _, 0x7) => self.subn_vx_vy(x, y),
-            (0x8, _, _, 0xE) => self.shl_vx_vy(x),
-            (0x9, _, _, 0x0) => self.sne_vx_vy(x, y),
-            (0xA, _, _, _) => self.ld_i_addr(nnn),
-            (0xB, _, _, _) => self.jp_v0_addr(nnn),
-            (0xC, _, _, _) => self.rnd_vx_byte(x, kk),
-            (0xD, _, _, _) => self.drw_vx_vy_nibble(x, y, n),
-            (0xE, _, 0x9, 0xE) => self.skp_vx(x),
-            (0xE, _, 0xA, 0x1) => self.sknp_vx(x),
-            (0xF, _, 0x0, 0x7) => self.ld_vx_dt(x),
-            (0xF, _, 0x0, 0xA) => self.ld_vx_k(x),
-            (0xF, _, 0x1, 0x5) => self.ld_dt_vx(x),
-            (0xF, _, 0x1, 0x8) => self.ld_st_vx(x),
-            (0xF, _, 0x1, 0xE) => self.add_i_vx(x),
-            (0xF, _, 0x2, 0x9) => self.ld_f_vx(x),
-            (0xF, _, 0x3, 0x3) => self.ld_b_vx(x),
-            (0xF, _, 0x5, 0x5) => self.ld_i_vx(x),
-            (0xF, _, 0x6, 0x5) => self.ld_vx_i(x),
-            _ => self.next_program(),
-        }
+    // Cleared pixels fade out over a few frames, hiding the flicker of sprites erased and redrawn
+    pub fn set_fade(&mut self, enabled: bool) {
+        self.fade = enabled.then(|| self.frame.iter().map(|row| row.iter().map(|&pixel| lit_intensity(pixel)).collect()).collect());
+        self.screen_dirty = true;
     }
 
-    // 00EE - RET
-    fn ret(&mut self) {
-        self.sp -= 1;
-        self.pc = self.stack[self.sp as usize] as u16;
-        self.next_program();
+    fn decay_fade(&mut self) {
+        let Some(fade) = &mut self.fade else { return };
+        // The display changed size, there's nothing left to fade
+        if fade.len() != self.height || fade[0].len() != self.width { *fade = vec![vec![0; self.width]; self.height]; }
+        for (intensities, pixels) in fade.iter_mut().zip(&self.frame) {
+            for (intensity, &pixel) in intensities.iter_mut().zip(pixels) {
+                let decayed = lit_intensity(pixel).max(intensity.saturating_sub(FADE_STEP));
+                if decayed != *intensity { self.screen_dirty = true; }
+                *intensity = decayed;
+            }
+        }
     }
 
-    // 00E0 - CLS
-    fn cls(&mut self) {
-        self.frame = [[0; FRAME_WIDTH]; FRAME_HEIGHT];
-        self.next_program();
+    // A fading pixel is blended from the background toward the first plane's color
+    fn pixel_color(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let planes = self.frame[y][x];
+        let intensity = self.fade.as_ref().and_then(|fade| fade.get(y)?.get(x).copied()).unwrap_or(0);
+        if planes != 0 || intensity == 0 { return self.palette.color(planes); }
+        blend(self.palette.color(0), self.palette.color(1), intensity)
     }
 
-    // 1nnn - JP addr
-    fn jp_addr(&mut self, nnn: u16) {
-        self.pc = nnn;
+    // Starts recording display changes for take_frame_delta
+    pub fn enable_frame_delta(&mut self) { self.frame_delta = Some(delta::full_frame(&self.frame)); }
+
+    // Changes since the last call, empty when recording isn't enabled
+    pub fn take_frame_delta(&mut self) -> Vec<FrameChange> {
+        self.frame_delta.as_mut().map(std::mem::take).unwrap_or_default()
     }
 
-    // 2nnn - CALL addr
-    fn call_addr(&mut self, nnn: u16) {
-        self.stack[self.sp as usize] = self.pc;
-        self.sp += 1;
-        self.pc = nnn;
+    // Pixel changes before a clear no longer matter, so the clear replaces them
+    fn record_clear(&mut self) {
+        let (width, height) = (self.width, self.height);
+        if let Some(changes) = &mut self.frame_delta { *changes = vec![FrameChange::Clear { width, height }]; }
     }
 
-    // 3xkk - SE Vx, byte
-    fn se_vx_byte(&mut self, x: u8, kk: u8) {
-        self.next_program();
-        if self.v[x as usize] == kk { self.next_program(); }
+    // Hash of the display contents, for comparing runs frame by frame
+    pub fn frame_hash(&self) -> u64 { fnv1a(&self.frame.concat()) }
+
+    // Replaces the built-in font with up to 16 glyphs of 5 bytes each
+    pub fn load_font(&mut self, font: &[u8]) {
+        assert!(!font.is_empty() && font.len() <= FONT_SET.len() && font.len().is_multiple_of(GLYPH_SIZE), "Invalid font size!");
+        self.memory[..FONT_SET.len()].fill(0);
+        self.memory[..font.len()].copy_from_slice(font);
+        if font.len() < FONT_SET.len() {
+            self.memory[font.len()..font.len() + GLYPH_SIZE].copy_from_slice(&MISSING_GLYPH);
+        }
+        self.font_glyphs = (font.len() / GLYPH_SIZE) as u8;
+    }
+
+    pub fn load_big_font(&mut self, font: &[u8]) {
+        assert!(font.len() <= MAX_BIG_GLYPHS * BIG_GLYPH_SIZE && font.len().is_multiple_of(BIG_GLYPH_SIZE), "Invalid big font size!");
+        self.memory[ADDR_BIG_FONT..ADDR_BIG_FONT + font.len()].copy_from_slice(font);
+        self.big_font_glyphs = (font.len() / BIG_GLYPH_SIZE) as u8;
+    }
+
+    pub fn set_missing_glyph_box(&mut self, enabled: bool) { self.missing_glyph_box = enabled; }
+
+    pub fn set_power_on_fill(&mut self, fill: u8) { self.power_on_fill = fill; }
+
+    // Restarts the loaded rom, the font and rom are left intact
+    pub fn reset(&mut self) {
+        self.v = [self.power_on_fill; 16];
+        let rom_end = ADDR_PROGRAM_START as usize + self.rom.len();
+        let font_end = ADDR_BIG_FONT + self.big_font_glyphs as usize * BIG_GLYPH_SIZE;
+        self.memory[font_end..ADDR_PROGRAM_START as usize].fill(self.power_on_fill);
+        // The rom may have written over itself
+        self.memory[ADDR_PROGRAM_START as usize..rom_end].copy_from_slice(&self.rom);
+        self.memory[rom_end..].fill(self.power_on_fill);
+        self.i = ADDR_PROGRAM_START;
+        self.stack = [0; 32];
+        self.sp = 0;
+        self.dt = 0;
+        self.st = 0;
+        self.set_hires(false);
+        self.frame = vec![vec![0; self.width]; self.height];
+        self.screen_dirty = true;
+        self.record_clear();
+        self.pc = ADDR_PROGRAM_START;
+        self.keypad.clear_keys();
+        self.fx0a_waiting = false;
+        self.display_waiting = false;
+        self.fault = None;
+    }
+
+    fn reload_changed_rom(&mut self) {
+        let Some(watcher) = &mut self.watcher else { return };
+        if !watcher.poll() { return; }
+        // The file may be mid-write, the next change will pick it up
+        // A rom grown past the memory is rejected and the running one carries on undisturbed
+        if let Ok(rom) = fs::read(watcher.path()) {
+            if self.load_rom_bytes(&rom).is_ok() { self.reset(); }
+        }
     }
 
-    // 4xkk - SNE Vx, byte
-    fn sne_vx_byte(&mut self, x: u8, kk: u8) {
-        self.next_program();
-        if self.v[x as usize] != kk { self.next_program(); }
+    // Returns true when the instruction was a draw that, with the display_wait quirk, has to be the
+    // last of the frame. pc is already past it either way
+    pub fn step(&mut self) -> bool {
+        if self.fault.is_some() { return false; }
+        if self.strict_alignment && self.pc % 2 == 1 {
+            self.fault = Some(Fault::MisalignedPc(self.pc));
+            return false;
+        }
+        let op_code = self.fetch_next_op_code();
+        self.instruction_count += 1;
+        if let Some(trace) = &mut self.trace {
+            // A failed write stops the trace rather than the machine, stop_trace reports it
+            if let Err(error) = trace.record(self.pc, op_code) {
+                self.trace = None;
+                self.trace_error = Some(error);
+            }
+        }
+        let before = self.v;
+        if self.safe_mode {
+            self.run_guarded(|chip| chip.run_op_code(op_code));
+        } else {
+            self.run_op_code(op_code);
+        }
+        self.check_register_watches(&before);
+        if self.legacy_timer_mode { self.tick_timers(); }
+        self.quirks.display_wait && self.fault.is_none() && op_code & 0xF000 == 0xD000
     }
 
-    // 5xy0 - SE Vx, Vy
-    fn se_vx_vy(&mut self, x: u8, y: u8) {
-        self.next_program();
-        if self.v[x as usize] == self.v[y as usize] { self.next_program(); }
+    // step for hosts that stop on the first fault, including one from before the call
+    pub fn try_step(&mut self) -> Result<bool, Chip8Error> {
+        let frame_done = self.step();
+        match self.fault {
+            Some(fault) => Err(Chip8Error::Fault { fault, pc: self.pc }),
+            None => Ok(frame_done),
+        }
     }
 
-    // 6xkk - LD Vx, byte
-    fn ld_vx_byte(&mut self, x: u8, kk: u8) {
-        self.v[x as usize] = kk;
-        self.next_program();
+    pub fn set_safe_mode(&mut self, enabled: bool) { self.safe_mode = enabled; }
+
+    pub fn set_break_on_collision(&mut self, enabled: bool) { self.break_on_collision = enabled; }
+
+    pub fn add_register_watch(&mut self, reg: u8, value: u8) { self.register_watches.push((reg & 0xF, value)); }
+
+    fn check_register_watches(&mut self, before: &[u8; 16]) {
+        if self.fault.is_some() { return; }
+        let hit = self.register_watches.iter()
+            .find(|&&(reg, value)| before[reg as usize] != value && self.v[reg as usize] == value);
+        if let Some(&(reg, value)) = hit { self.fault = Some(Fault::RegisterWatch { reg, value }); }
     }
 
-    // 7xkk - ADD Vx, byte
-    fn add_vx_byte(&mut self, x: u8, kk: u8) {
-        self.v[x as usize] = self.v[x as usize].overflowing_add(kk).0;
-        self.next_program();
+    // Standard CHIP-8 allows an odd pc, reached through Bnnn or jumps, and fetches the word there.
+    // Strict mode treats it as a fault instead, as it's almost always a bug
+    pub fn set_strict_alignment(&mut self, enabled: bool) { self.strict_alignment = enabled; }
+
+    pub fn set_legacy_timer_mode(&mut self, enabled: bool) { self.legacy_timer_mode = enabled; }
+
+    fn run_guarded(&mut self, f: impl FnOnce(&mut Self)) {
+        if panic::catch_unwind(AssertUnwindSafe(|| f(self))).is_err() {
+            self.fault = Some(Fault::InternalPanic);
+        }
     }
 
-    // 8xy0 - LD Vx, Vy
-    fn ld_vx_vy(&mut self, x: u8, y: u8) {
-        self.v[x as usize] = self.v[y as usize];
-        self.next_program();
+    pub fn start_trace(&mut self, path: &str) -> io::Result<()> {
+        self.trace = Some(TraceWriter::create(path)?);
+        self.trace_error = None;
+        Ok(())
     }
 
-    // 8xy1 - OR Vx, Vy
-    fn or_vx_vy(&mut self, x: u8, y: u8) {
-        self.v[x as usize] |= self.v[y as usize];
-        self.next_program();
+    // Flushes the trace, or returns the error that stopped it early
+    pub fn stop_trace(&mut self) -> io::Result<()> {
+        if let Some(error) = self.trace_error.take() { return Err(error); }
+        match self.trace.take() {
+            Some(mut trace) => trace.flush(),
+            None => Ok(()),
+        }
     }
 
-    // 8xy2 - AND Vx, Vy
-    fn and_vx_vy(&mut self, x: u8, y: u8) {
-        self.v[x as usize] &= self.v[y as usize];
-        self.next_program();
+    // None of step, tick_timers and step_frame read the clock, so a host with its own 60Hz loop can
+    // drive the machine by calling tick_timers exactly 60 times a second and step as often as it
+    // likes, or just step_frame 60 times a second. Only start_cycle keeps time itself
+    pub fn step_frame(&mut self) {
+        if self.paused { return; }
+        self.save_snapshot();
+        for _ in 0..CYCLES_PER_FRAME {
+            if self.step() { break; }
+        }
+        self.end_frame();
+    }
+
+    fn end_frame(&mut self) {
+        // Recorded as the rom played it, muting only silences the live tone
+        let sounding = self.st > 0 && self.st >= self.min_sound_timer;
+        if let Some(timeline) = &mut self.sound_timeline { timeline.push(sounding); }
+        if !self.legacy_timer_mode { self.tick_timers(); }
+        self.display_waiting = false;
+        if let Some((_, recorder)) = &mut self.recording { recorder.capture(&self.frame); }
+        self.decay_fade();
+        self.update_tone();
+        self.frame_count += 1;
+    }
+
+    pub fn enable_rewind(&mut self) { self.rewind = Some(VecDeque::with_capacity(REWIND_FRAMES)); }
+
+    // Restores the machine to the start of the last frame run, returns false when there's none left
+    pub fn step_frame_back(&mut self) -> bool {
+        let Some(snapshot) = self.rewind.as_mut().and_then(|rewind| rewind.pop_back()) else { return false };
+        self.v = snapshot.v;
+        self.i = snapshot.i;
+        self.stack = snapshot.stack;
+        self.sp = snapshot.sp;
+        self.dt = snapshot.dt;
+        self.st = snapshot.st;
+        self.frame = snapshot.frame;
+        self.hires = snapshot.hires;
+        (self.width, self.height) = (self.frame[0].len(), self.frame.len());
+        self.resize_window();
+        if let Some(changes) = &mut self.frame_delta { *changes = delta::full_frame(&self.frame); }
+        self.pc = snapshot.pc;
+        self.memory = snapshot.memory;
+        self.frame_count = snapshot.frame_count;
+        self.fault = None;
+        true
+    }
+
+    fn save_snapshot(&mut self) {
+        let Some(rewind) = &mut self.rewind else { return };
+        if rewind.len() == REWIND_FRAMES { rewind.pop_front(); }
+        rewind.push_back(Snapshot {
+            v: self.v,
+            i: self.i,
+            stack: self.stack,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+            frame: self.frame.clone(),
+            hires: self.hires,
+            pc: self.pc,
+            memory: self.memory,
+            frame_count: self.frame_count,
+        });
+    }
+
+    // Runs frames back to back as fast as possible, for benchmarking
+    pub fn run_unthrottled(&mut self, frames: u64) {
+        let start = Instant::now();
+        for _ in 0..frames { self.step_frame(); }
+        self.busy_time += start.elapsed();
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        Metrics { instructions: self.instruction_count, frames: self.frame_count, elapsed: self.busy_time }
+    }
+
+    pub fn tick_timers(&mut self) {
+        if self.paused { return; }
+        if self.dt > 0 { self.dt -= 1; }
+        if self.st > 0 { self.st -= 1; }
+        if let Some(handler) = &mut self.on_timer_tick { handler(self.dt, self.st); }
+        self.fx0a_latch = match self.fx0a_latch {
+            Some((key, ticks)) if ticks > 1 => Some((key, ticks - 1)),
+            _ => None,
+        };
     }
 
-    // 8xy3 - XOR Vx, Vy
-    fn xor_vx_vy(&mut self, x: u8, y: u8) {
-        self.v[x as usize] ^= self.v[y as usize];
-        self.next_program();
+    pub fn fault(&self) -> Option<Fault> { self.fault }
+
+    // Return addresses of the active calls, outermost first
+    pub fn call_stack(&self) -> &[u16] { &self.stack[..self.sp as usize] }
+
+    pub fn state(&self) -> MachineState {
+        MachineState { v: self.v, i: self.i, pc: self.pc, sp: self.sp, dt: self.dt, st: self.st }
     }
 
-    // 8xy4 - ADD Vx, Vy
-    fn add_vx_vy(&mut self, x: u8, y: u8) {
-        let (sum, overflow) = self.v[x as usize].overflowing_add(self.v[y as usize]);
-        self.v[x as usize] = sum;
-        self.v[0xF] = overflow as u8;
-        self.next_program();
+    // Decode of the instruction about to run, without counting it as executed
+    pub fn current_opcode(&self) -> Option<Opcode> { opcode::decode(self.word_at_pc()) }
+
+    fn word_at_pc(&self) -> u16 { self.word_at(self.pc) }
+
+    // Wraps around the end of memory the way instruction fetches do
+    fn word_at(&self, addr: u16) -> u16 {
+        let addr = addr as usize & 0xFFF;
+        u16::from_be_bytes([self.memory[addr], self.memory[(addr + 1) & 0xFFF]])
     }
 
-    // 8xy5 - SUB Vx, Vy
-    fn sub_vx_vy(&mut self, x: u8, y: u8) {
-        let (result, overflow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
-        self.v[x as usize] = result;
-        self.v[0xF] = !overflow as u8;
-        self.next_program();
+    // Mnemonics for count words from start, decoded the same way run_op_code does
+    pub fn disassemble(&self, start: u16, count: usize) -> Vec<(u16, String)> {
+        (0..count)
+            .map(|n| {
+                let addr = start.wrapping_add(2 * n as u16) & 0xFFF;
+                (addr, opcode::mnemonic(self.word_at(addr)))
+            })
+            .collect()
     }
 
-    // 8xy6 - SHR Vx {, Vy}
-    fn shr_vx_vy(&mut self, x: u8) {
-        self.v[0xF] = (self.v[x as usize] & 1 == 1) as u8;
-        self.v[x as usize] >>= 1;
-        self.next_program();
+    pub fn max_written_addr(&self) -> u16 { self.max_written_addr }
+
+    pub fn last_collision_count(&self) -> u32 { self.last_collision_count }
+
+    pub fn set_debug_hud(&mut self, enabled: bool) {
+        self.debug_hud = enabled;
+        self.screen_dirty = true;
     }
 
-    // 8xy7 - SUBN Vx, Vy
-    fn subn_vx_vy(&mut self, x: u8, y: u8) {
-        let (result, overflow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
-        self.v[0xF] = !overflow as u8;
-        self.v[x as usize] = result;
-        self.next_program();
+    // Shows the registers in a panel right of the display
+    pub fn set_debug_panel(&mut self, enabled: bool) {
+        self.debug_panel = enabled;
+        self.resize_window();
     }
 
-    // 8xyE - SHL Vx {, Vy}
-    fn shl_vx_vy(&mut self, x: u8) {
-        self.v[0xF] = (self.v[x as usize] >> 7 == 1) as u8;
-        self.v[x as usize] <<= 1;
-        self.next_program();
+    pub fn set_ruler(&mut self, enabled: bool) {
+        self.ruler = enabled;
+        self.screen_dirty = true;
     }
 
-    // 9xy0 - SNE Vx, Vy
-    fn sne_vx_vy(&mut self, x: u8, y: u8) {
-        self.next_program();
-        if self.v[x as usize] != self.v[y as usize] { self.next_program(); }
+    // Shows the keypad state in a panel below the display
+    pub fn set_show_keys(&mut self, enabled: bool) {
+        self.show_keys = enabled;
+        self.resize_window();
     }
 
-    // Annn - LD I, addr
-    fn ld_i_addr(&mut self, nnn: u16) {
-        self.i = nnn;
-        self.next_program();
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.screen_dirty = true;
     }
 
-    // Bnnn - JP V0, addr
-    fn jp_v0_addr(&mut self, nnn: u16) {
-        self.pc = (self.v[0] as u16 + nnn).min(0xFFF);
+    pub fn scale(&self) -> u32 { self.scale }
+
+    // Resizes the existing window, keeping its position and focus
+    pub fn set_scale(&mut self, scale: u32) {
+        self.scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        self.resize_window();
     }
 
-    // Cxkk - RND Vx, byte
-    fn rnd_vx_byte(&mut self, x: u8, kk: u8) {
-        self.v[x as usize] = rand::random::<u8>() & kk;
-        self.next_program();
+    // Uses the largest scale whose display fits in the given window width
+    pub fn fit_window_width(&mut self, window_width: u32) { self.set_scale(scale_for_width(self.width, window_width)); }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        let (mut width, mut height) = self.display_size();
+        if self.show_keys { height += KEYPAD_PANEL_HEIGHT; }
+        if self.debug_panel { width += DEBUG_PANEL_WIDTH; }
+        (width, height)
     }
 
-    // Dxyn - DRW Vx, Vy, nibble
-    fn drw_vx_vy_nibble(&mut self, x: u8, y: u8, n: u8) {
-        self.v[0xF] = 0;
-        for byte in 0..n {
-            let y = (self.v[y as usize].overflowing_add(byte).0 % 32) as usize;
-            let sprite = self.memory[(self.i + byte as u16) as usize];
-            for bit in 0..8 {
-                let x = (self.v[x as usize].overflowing_add(bit).0 % 64) as usize;
-                let pixel = (sprite >> (7 - bit)) & 1;
-                self.v[0xF] |= self.frame[y][x] & pixel;
-                self.frame[y][x] ^= pixel;
-            }
+    fn display_size(&self) -> (u32, u32) { (self.width as u32 * self.scale, self.height as u32 * self.scale) }
+
+    fn resize_window(&mut self) {
+        let (width, height) = self.window_size();
+        self.screen_dirty = true;
+        if let Some(canvas) = &mut self.canvas {
+            canvas.window_mut().set_size(width, height).expect("Could not resize Window!");
         }
-        self.next_program();
     }
 
-    // Ex9E - SKP Vx
-    fn skp_vx(&mut self, x: u8) {
-        self.next_program();
-        if self.keypad.is_pressed(self.v[x as usize]) { self.next_program(); }
+    pub fn pressed_keys(&self) -> u16 { self.keypad.get_state() }
+
+    pub fn enable_profiling(&mut self) {
+        let size = self.memory.len();
+        self.profile = Some(Profile { reads: vec![0; size], writes: vec![0; size], executes: vec![0; size] });
     }
 
-    // ExA1 - SKNP Vx
-    fn sknp_vx(&mut self, x: u8) {
-        self.next_program();
-        if !self.keypad.is_pressed(self.v[x as usize]) { self.next_program(); }
+    pub fn is_beeping(&self) -> bool { self.st > 0 && !self.muted && self.st >= self.min_sound_timer }
+
+    // Pauses the audio device too, so a muted machine runs no audio callbacks
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        if let Some(device) = &self.audio {
+            if muted { device.pause() } else { device.resume() }
+        }
+        self.update_tone();
     }
 
-    // Fx07 - LD Vx, DT
-    fn ld_vx_dt(&mut self, x: u8) {
-        self.v[x as usize] = self.dt;
-        self.next_program();
+    pub fn set_tone_frequency(&mut self, frequency: f32) {
+        if let Some(device) = &mut self.audio { device.lock().frequency = frequency; }
     }
 
-    // Fx0A - LD Vx, K
-    fn ld_vx_k(&mut self, x: u8) {
-        if let Some(key) = self.keypad.get_key() {
-            self.v[x as usize] = key;
-            self.next_program();
-        }
+    pub fn set_volume(&mut self, volume: f32) {
+        if let Some(device) = &mut self.audio { device.lock().volume = volume.clamp(0.0, 1.0); }
     }
 
-    // Fx15 - LD DT, Vx
-    fn ld_dt_vx(&mut self, x: u8) {
-        self.dt = self.v[x as usize];
-        self.next_program();
+    // Sounds the tone for duration, blocking. Returns false when muted or without an audio device
+    pub fn play_tone(&mut self, duration: Duration) -> bool {
+        if self.muted { return false; }
+        let Some(device) = &mut self.audio else { return false };
+        device.lock().on = true;
+        thread::sleep(duration);
+        device.lock().on = false;
+        true
     }
 
-    // Fx18 - LD ST, Vx
-    fn ld_st_vx(&mut self, x: u8) {
-        self.st = self.v[x as usize];
-        self.next_program();
+    fn update_tone(&mut self) {
+        let on = self.is_beeping() && !self.paused;
+        if let Some(device) = &mut self.audio { device.lock().on = on; }
+    }
+
+    // Some interpreters stay silent for very short beeps, e.g. the COSMAC VIP for a sound timer of 1
+    pub fn set_min_sound_timer(&mut self, value: u8) { self.min_sound_timer = value; }
+
+    pub fn enable_audio_recording(&mut self) { self.sound_timeline = Some(Vec::new()); }
+
+    // Writes the tone the sound timer played over the recorded frames as a WAV file
+    #[cfg(feature = "record-audio")]
+    pub fn export_audio(&self, path: &str) -> io::Result<()> {
+        let timeline = self.sound_timeline.as_ref()
+            .ok_or_else(|| io::Error::other("Audio recording is not enabled!"))?;
+        let samples = wav::square_wave(timeline, wav::SAMPLE_RATE, wav::TONE_FREQUENCY);
+        fs::write(path, wav::encode(wav::SAMPLE_RATE, &samples))
+    }
+
+    pub fn export_profile(&self, path: &str) -> io::Result<()> {
+        let profile = self.profile.as_ref()
+            .ok_or_else(|| io::Error::other("Profiling is not enabled!"))?;
+        let mut csv = String::from("address,reads,writes,executes\n");
+        for addr in 0..self.memory.len() {
+            csv += &format!("{:#05X},{},{},{}\n",
+                addr, profile.reads[addr], profile.writes[addr], profile.executes[addr]);
+        }
+        fs::write(path, csv)
+    }
+
+    pub fn set_keypad_state(&mut self, keys: u16) { self.keypad.set_state(keys); }
+
+    pub fn set_key_map(&mut self, key_map: KeyMap) { self.keypad.set_key_map(key_map); }
+
+    pub fn set_quit_key(&mut self, key: Keycode) { self.quit_key = key; }
+
+    // Steps until the keypad goes from no keys to any key down, read_keys being polled before each
+    // step. Returns whether that happened within max_steps, a fault also stops it
+    pub fn run_until_input(&mut self, max_steps: u32, mut read_keys: impl FnMut() -> u16) -> bool {
+        let mut idle = false;
+        for _ in 0..max_steps {
+            let keys = read_keys();
+            self.set_keypad_state(keys);
+            if idle && keys != 0 { return true; }
+            if self.fault.is_some() { return false; }
+            idle = keys == 0;
+            self.step();
+        }
+        false
+    }
+
+    // Each script entry is (frame, keys), keys being a bitset of the CHIP-8 keys held down
+    // from that frame on
+    pub fn run_script(&mut self, frames: &[(u64, u16)], total_frames: u64) {
+        for _ in 0..total_frames {
+            if let Some(&(_, keys)) = frames.iter().find(|(frame, _)| *frame == self.frame_count) {
+                self.set_keypad_state(keys);
+            }
+            self.step_frame();
+        }
+    }
+
+    pub fn start_cycle(&mut self) {
+        let mut timestep = FixedTimestep::new(timing::step_interval(self.timer_hz));
+        timestep.set_max_steps(self.max_catch_up_frames);
+        let mut cpu_timestep = self.target_ips.map(|ips| {
+            let mut cpu_timestep = FixedTimestep::new(timing::step_interval(ips));
+            cpu_timestep.set_max_steps(self.max_catch_up_frames.saturating_mul(ips.div_ceil(60)));
+            cpu_timestep
+        });
+        let mut last_update = Instant::now();
+        let mut last_screenshot = Instant::now();
+
+        'cycle: loop {
+            if self.handle_events() { break 'cycle; }
+            self.reload_changed_rom();
+
+            let now = Instant::now();
+            let elapsed = now - last_update;
+            last_update = now;
+
+            if self.waiting_for_key {
+                self.show_wait_message();
+                continue;
+            }
+
+            self.advance_clocks(elapsed, &mut timestep, cpu_timestep.as_mut());
+            // A present waits for vsync, without one the loop would spin while paused or idle
+            if !self.update_screen() {
+                let cpu_remaining = cpu_timestep.as_ref().map_or(Duration::MAX, FixedTimestep::remaining);
+                thread::sleep(timestep.remaining().min(cpu_remaining));
+            }
+
+            if let Some(interval) = self.screenshot_interval {
+                if screenshot_due(last_screenshot.elapsed(), interval) {
+                    last_screenshot = Instant::now();
+                    self.screenshot_count += 1;
+                    let path = format!("screenshot-{:05}.png", self.screenshot_count);
+                    // Later ones would most likely fail the same way, so the first failure ends them
+                    if let Err(error) = self.screenshot(&path) {
+                        eprintln!("Could not save screenshot, no more will be taken: {}", error);
+                        self.screenshot_interval = None;
+                    }
+                }
+            }
+        }
+    }
+
+    // Runs the frames and, at a target rate, the instructions due after `elapsed`. Frames tick the
+    // timers at the timer rate however many instructions ran
+    fn advance_clocks(&mut self, elapsed: Duration, timestep: &mut FixedTimestep, cpu_timestep: Option<&mut FixedTimestep>) {
+        let Some(cpu_timestep) = cpu_timestep else {
+            for _ in 0..timestep.advance(elapsed) { self.step_frame(); }
+            return;
+        };
+        if self.paused { return; }
+        for _ in 0..cpu_timestep.advance(elapsed) {
+            if self.display_waiting { break; }
+            self.display_waiting = self.step();
+        }
+        for _ in 0..timestep.advance(elapsed) {
+            self.end_frame();
+            self.save_snapshot();
+        }
+    }
+
+    fn handle_events(&mut self) -> bool {
+        let Some(event_pump) = &mut self.event_pump else { return false };
+        let events: Vec<Event> = event_pump.poll_iter().collect();
+        let mut quit = false;
+        for event in events { quit |= self.handle_event(&event); }
+        quit
+    }
+
+    // Returns true if the event asks to quit. The quit key never reaches the keypad, even when the
+    // key map binds it
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        // The keypad panel follows key presses, and exposed windows lose their content
+        if matches!(event, Event::KeyDown { .. } | Event::KeyUp { .. } | Event::Window { .. }) {
+            self.screen_dirty = true;
+        }
+        match event {
+            Event::Quit { .. } => return true,
+            Event::KeyDown { keycode: Some(key), .. } if *key == self.quit_key => return true,
+            // Space pauses rather than reaching the keypad, even when the key map binds it
+            Event::KeyDown { keycode: Some(Keycode::Space), repeat: false, .. } => self.set_paused(!self.paused),
+            // Restarts a rom that hung or finished
+            Event::KeyDown { keycode: Some(Keycode::F1), repeat: false, .. } => self.reset(),
+            Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                // A full disk or unwritable directory shouldn't end the game
+                if let Err(error) = self.screenshot(&timestamped_path("screenshot", "png", SystemTime::now())) {
+                    eprintln!("Could not save screenshot: {}", error);
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } => {
+                if self.is_recording() {
+                    if let Err(error) = self.stop_recording() { eprintln!("Could not save recording: {}", error); }
+                } else {
+                    self.start_recording(&timestamped_path("recording", "gif", SystemTime::now()));
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::Equals | Keycode::Plus | Keycode::KpPlus), .. } => {
+                self.set_scale(self.scale + 1);
+            }
+            Event::KeyDown { keycode: Some(Keycode::Minus | Keycode::KpMinus), .. } => {
+                self.set_scale(self.scale - 1);
+            }
+            Event::KeyDown { keycode, .. } => {
+                self.waiting_for_key = false;
+                if let Some(key) = keycode { self.keypad.down_key(*key); }
+            }
+            Event::KeyUp { keycode: Some(key), .. } => self.keypad.release_key(*key),
+            // Key ups while unfocused are lost, so nothing is left held down
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => self.keypad.clear_keys(),
+            _ => {}
+        }
+        false
+    }
+
+    fn show_wait_message(&mut self) {
+        let (width, height) = self.display_size();
+        let Some(canvas) = &mut self.canvas else { return };
+        canvas.set_draw_color(Color::from(RGB_BLACK));
+        canvas.clear();
+        let (x, y) = ((width / 2) as i32 - 128, (height / 2) as i32 - 10);
+        hud::draw_text(canvas, "PRESS ANY KEY", x, y, 4, Color::from(RGB_WHITE));
+        canvas.present();
+        self.screen_dirty = true;
+    }
+
+    fn fetch_next_op_code(&mut self) -> u16 {
+        let pc = self.pc as usize & 0xFFF;
+        if let Some(profile) = &mut self.profile { profile.executes[pc] += 1; }
+        ((self.memory[pc] as u16) << 8) | self.memory[(pc + 1) & 0xFFF] as u16
+    }
+
+    // Draws the display to the window once, for hosts that don't run start_cycle
+    pub fn present(&mut self) {
+        self.screen_dirty = true;
+        self.update_screen();
+    }
+
+    // Skips idle cycles entirely. Changed cells alone can't be repainted since SDL leaves the back
+    // buffer undefined after a present, so a redraw always covers the whole window
+    // Returns whether the display was drawn, headless machines count the rects without a window
+    fn update_screen(&mut self) -> bool {
+        // The debug panel shows the registers, which change on nearly every instruction
+        if !std::mem::take(&mut self.screen_dirty) && !self.debug_panel { return false; }
+        let mut canvas = self.canvas.take();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.fill_rects += 1;
+                let Some(canvas) = &mut canvas else { continue };
+                let color = Color::from(self.pixel_color(x, y));
+
+                canvas.set_draw_color(color);
+                canvas.fill_rect(Rect::new(
+                    (x as u32 * self.scale) as i32,
+                    (y as u32 * self.scale) as i32,
+                    self.scale,
+                    self.scale,
+                )).expect("Fill Rect Issue");
+            }
+        }
+        let Some(mut canvas) = canvas else { return true };
+        if self.ruler { self.draw_ruler(&mut canvas); }
+        if self.show_keys { self.draw_keypad(&mut canvas); }
+        if self.debug_panel { self.draw_debug_panel(&mut canvas); }
+        if self.debug_hud {
+            let text = self.last_collision_count.to_string();
+            hud::draw_text(&mut canvas, &text, 4, 4, 2, Color::from(RGB_HUD));
+        }
+        canvas.present();
+        self.canvas = Some(canvas);
+        true
+    }
+
+    fn draw_debug_panel(&self, canvas: &mut Canvas<Window>) {
+        let (width, _) = self.display_size();
+        let (_, height) = self.window_size();
+        canvas.set_draw_color(Color::from(RGB_BLACK));
+        canvas.fill_rect(Rect::new(width as i32, 0, DEBUG_PANEL_WIDTH, height)).expect("Fill Rect Issue");
+
+        // The instruction about to run, to follow along at a slow --speed
+        let mut lines = register_panel(&self.state());
+        lines.push(format!("OP:{:04X}", self.word_at_pc()));
+        for (row, line) in lines.iter().enumerate() {
+            let y = 4 + (row as u32 * DEBUG_PANEL_LINE_HEIGHT) as i32;
+            hud::draw_text(canvas, line, width as i32 + 8, y, DEBUG_PANEL_TEXT_SCALE, Color::from(RGB_WHITE));
+        }
+    }
+
+    fn draw_keypad(&self, canvas: &mut Canvas<Window>) {
+        canvas.set_draw_color(Color::from(RGB_BLACK));
+        let (width, height) = self.display_size();
+        canvas.fill_rect(Rect::new(0, height as i32, width, KEYPAD_PANEL_HEIGHT)).expect("Fill Rect Issue");
+
+        let left = (width as i32 - 4 * KEY_CELL_WIDTH as i32) / 2;
+        let top = (height + (KEYPAD_PANEL_HEIGHT - 4 * KEY_CELL_HEIGHT) / 2) as i32;
+        for (row, keys) in keypad_grid(self.pressed_keys()).iter().enumerate() {
+            for (col, &(key, pressed)) in keys.iter().enumerate() {
+                let x = left + (col as u32 * KEY_CELL_WIDTH) as i32;
+                let y = top + (row as u32 * KEY_CELL_HEIGHT) as i32;
+                let (cell, text) = if pressed { (RGB_KEY_DOWN, RGB_BLACK) } else { (RGB_KEY_UP, RGB_WHITE) };
+                canvas.set_draw_color(Color::from(cell));
+                canvas.fill_rect(Rect::new(x, y, KEY_CELL_WIDTH - 1, KEY_CELL_HEIGHT - 1))
+                    .expect("Fill Rect Issue");
+                hud::draw_text(canvas, &format!("{:X}", key), x + 4, y + 2, 2, Color::from(text));
+            }
+        }
+    }
+
+    // Tick marks along the top and left edges, drawn over the display only
+    fn draw_ruler(&self, canvas: &mut Canvas<Window>) {
+        let (columns, rows) = ruler_ticks(self.width, self.height, self.scale);
+        let length = self.scale;
+        canvas.set_draw_color(Color::from(RGB_RULER));
+        for x in columns {
+            canvas.fill_rect(Rect::new(x, 0, 1, length)).expect("Fill Rect Issue");
+        }
+        for y in rows {
+            canvas.fill_rect(Rect::new(0, y, length, 1)).expect("Fill Rect Issue");
+        }
+    }
+
+    fn run_op_code(&mut self, code: u16) {
+        match opcode::decode(code) {
+            Some(Opcode::Ret) => self.ret(),
+            Some(Opcode::Cls) => self.cls(),
+            Some(Opcode::ScdNibble { n }) => self.scd_nibble(n),
+            Some(Opcode::Scr) => self.scr(),
+            Some(Opcode::Scl) => self.scl(),
+            Some(Opcode::Low) => self.low(),
+            Some(Opcode::High) => self.high(),
+            Some(Opcode::JpAddr { nnn }) => self.jp_addr(nnn),
+            Some(Opcode::CallAddr { nnn }) => self.call_addr(nnn),
+            Some(Opcode::SeVxByte { x, kk }) => self.se_vx_byte(x, kk),
+            Some(Opcode::SneVxByte { x, kk }) => self.sne_vx_byte(x, kk),
+            Some(Opcode::SeVxVy { x, y }) => self.se_vx_vy(x, y),
+            Some(Opcode::LdVxByte { x, kk }) => self.ld_vx_byte(x, kk),
+            Some(Opcode::AddVxByte { x, kk }) => self.add_vx_byte(x, kk),
+            Some(Opcode::LdVxVy { x, y }) => self.ld_vx_vy(x, y),
+            Some(Opcode::OrVxVy { x, y }) => self.or_vx_vy(x, y),
+            Some(Opcode::AndVxVy { x, y }) => self.and_vx_vy(x, y),
+            Some(Opcode::XorVxVy { x, y }) => self.xor_vx_vy(x, y),
+            Some(Opcode::AddVxVy { x, y }) => self.add_vx_vy(x, y),
+            Some(Opcode::SubVxVy { x, y }) => self.sub_vx_vy(x, y),
+            Some(Opcode::ShrVxVy { x, y }) => self.shr_vx_vy(x, y),
+            Some(Opcode::SubnVxVy { x, y }) => self.subn_vx_vy(x, y),
+            Some(Opcode::ShlVxVy { x, y }) => self.shl_vx_vy(x, y),
+            Some(Opcode::SneVxVy { x, y }) => self.sne_vx_vy(x, y),
+            Some(Opcode::LdIAddr { nnn }) => self.ld_i_addr(nnn),
+            Some(Opcode::JpV0Addr { x, nnn }) => self.jp_v0_addr(x, nnn),
+            Some(Opcode::RndVxByte { x, kk }) => self.rnd_vx_byte(x, kk),
+            Some(Opcode::DrwVxVyNibble { x, y, n }) => self.drw_vx_vy_nibble(x, y, n),
+            Some(Opcode::SkpVx { x }) => self.skp_vx(x),
+            Some(Opcode::SknpVx { x }) => self.sknp_vx(x),
+            Some(Opcode::LdVxDt { x }) => self.ld_vx_dt(x),
+            Some(Opcode::LdVxK { x }) => self.ld_vx_k(x),
+            Some(Opcode::LdDtVx { x }) => self.ld_dt_vx(x),
+            Some(Opcode::LdStVx { x }) => self.ld_st_vx(x),
+            Some(Opcode::AddIVx { x }) => self.add_i_vx(x),
+            Some(Opcode::LdFVx { x }) => self.ld_f_vx(x),
+            Some(Opcode::LdHfVx { x }) => self.ld_hf_vx(x),
+            Some(Opcode::LdBVx { x }) => self.ld_b_vx(x),
+            Some(Opcode::LdIVx { x }) => self.ld_i_vx(x),
+            Some(Opcode::LdVxI { x }) => self.ld_vx_i(x),
+            None => {
+                if let Some(log) = &mut self.invalid_log {
+                    if !log.contains(&(self.pc, code)) { log.push((self.pc, code)); }
+                }
+                self.next_program();
+            }
+        }
+    }
+
+    // 00EE - RET
+    fn ret(&mut self) {
+        if self.sp == 0 {
+            self.fault = Some(Fault::StackUnderflow);
+            return;
+        }
+        let addr = self.stack[self.sp as usize - 1];
+        if !(ADDR_PROGRAM_START..=ADDR_LAST_INSTRUCTION).contains(&addr.wrapping_add(2)) {
+            self.fault = Some(Fault::InvalidReturn(addr));
+            return;
+        }
+        self.sp -= 1;
+        self.pc = addr;
+        self.next_program();
+    }
+
+    // 00E0 - CLS
+    fn cls(&mut self) {
+        self.frame = vec![vec![0; self.width]; self.height];
+        self.screen_dirty = true;
+        self.record_clear();
+        self.next_program();
+    }
+
+    // 1nnn - JP addr
+    fn jp_addr(&mut self, nnn: u16) {
+        self.pc = nnn;
+    }
+
+    // 2nnn - CALL addr
+    fn call_addr(&mut self, nnn: u16) {
+        if self.sp as usize >= self.stack.len() {
+            self.fault = Some(Fault::StackOverflow);
+            return;
+        }
+        self.stack[self.sp as usize] = self.pc;
+        self.sp += 1;
+        self.pc = nnn;
+    }
+
+    // 3xkk - SE Vx, byte
+    fn se_vx_byte(&mut self, x: u8, kk: u8) {
+        self.next_program();
+        if self.reg(x) == kk { self.next_program(); }
+    }
+
+    // 4xkk - SNE Vx, byte
+    fn sne_vx_byte(&mut self, x: u8, kk: u8) {
+        self.next_program();
+        if self.reg(x) != kk { self.next_program(); }
+    }
+
+    // 5xy0 - SE Vx, Vy
+    fn se_vx_vy(&mut self, x: u8, y: u8) {
+        self.next_program();
+        if self.reg(x) == self.reg(y) { self.next_program(); }
+    }
+
+    // 6xkk - LD Vx, byte
+    fn ld_vx_byte(&mut self, x: u8, kk: u8) {
+        *self.reg_mut(x) = kk;
+        self.next_program();
+    }
+
+    // 7xkk - ADD Vx, byte
+    fn add_vx_byte(&mut self, x: u8, kk: u8) {
+        *self.reg_mut(x) = self.reg(x).overflowing_add(kk).0;
+        self.next_program();
+    }
+
+    // 8xy0 - LD Vx, Vy
+    fn ld_vx_vy(&mut self, x: u8, y: u8) {
+        *self.reg_mut(x) = self.reg(y);
+        self.next_program();
+    }
+
+    // 8xy1 - OR Vx, Vy
+    fn or_vx_vy(&mut self, x: u8, y: u8) {
+        *self.reg_mut(x) |= self.reg(y);
+        self.next_program();
+    }
+
+    // 8xy2 - AND Vx, Vy
+    fn and_vx_vy(&mut self, x: u8, y: u8) {
+        *self.reg_mut(x) &= self.reg(y);
+        self.next_program();
+    }
+
+    // 8xy3 - XOR Vx, Vy
+    fn xor_vx_vy(&mut self, x: u8, y: u8) {
+        *self.reg_mut(x) ^= self.reg(y);
+        self.next_program();
+    }
+
+    // 8xy4 - ADD Vx, Vy
+    fn add_vx_vy(&mut self, x: u8, y: u8) {
+        let (sum, overflow) = self.reg(x).overflowing_add(self.reg(y));
+        *self.reg_mut(x) = sum;
+        self.v[0xF] = overflow as u8;
+        self.next_program();
+    }
+
+    // 8xy5 - SUB Vx, Vy
+    fn sub_vx_vy(&mut self, x: u8, y: u8) {
+        let (result, overflow) = self.reg(x).overflowing_sub(self.reg(y));
+        *self.reg_mut(x) = result;
+        self.v[0xF] = !overflow as u8;
+        self.next_program();
+    }
+
+    // 8xy6 - SHR Vx {, Vy}
+    fn shr_vx_vy(&mut self, x: u8, y: u8) {
+        let source = self.shift_source(x, y);
+        *self.reg_mut(x) = source >> 1;
+        // Set last so that with Vx being VF the carried out bit wins
+        self.v[0xF] = source & 1;
+        self.next_program();
+    }
+
+    // 8xy7 - SUBN Vx, Vy
+    fn subn_vx_vy(&mut self, x: u8, y: u8) {
+        let (result, overflow) = self.reg(y).overflowing_sub(self.reg(x));
+        self.v[0xF] = !overflow as u8;
+        *self.reg_mut(x) = result;
+        self.next_program();
+    }
+
+    // 8xyE - SHL Vx {, Vy}
+    fn shl_vx_vy(&mut self, x: u8, y: u8) {
+        let source = self.shift_source(x, y);
+        *self.reg_mut(x) = source << 1;
+        self.v[0xF] = source >> 7;
+        self.next_program();
+    }
+
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy { self.reg(y) } else { self.reg(x) }
+    }
+
+    // 9xy0 - SNE Vx, Vy
+    fn sne_vx_vy(&mut self, x: u8, y: u8) {
+        self.next_program();
+        if self.reg(x) != self.reg(y) { self.next_program(); }
+    }
+
+    // Annn - LD I, addr
+    fn ld_i_addr(&mut self, nnn: u16) {
+        self.i = nnn;
+        self.next_program();
+    }
+
+    // Bnnn - JP V0, addr
+    fn jp_v0_addr(&mut self, x: u8, nnn: u16) {
+        let offset = if self.quirks.jump_uses_vx { self.reg(x) } else { self.v[0] };
+        self.pc = (offset as u16 + nnn).min(0xFFF);
+    }
+
+    // 00Cn - SCD nibble
+    fn scd_nibble(&mut self, n: u8) {
+        let rows = self.scroll_distance(n as usize).min(self.height);
+        self.frame.rotate_right(rows);
+        self.frame[..rows].iter_mut().for_each(|row| row.fill(0));
+        self.frame_scrolled();
+        self.next_program();
+    }
+
+    // 00FB - SCR
+    fn scr(&mut self) {
+        let columns = self.scroll_distance(4);
+        for row in &mut self.frame {
+            row.rotate_right(columns);
+            row[..columns].fill(0);
+        }
+        self.frame_scrolled();
+        self.next_program();
+    }
+
+    // 00FC - SCL
+    fn scl(&mut self) {
+        let columns = self.scroll_distance(4);
+        for row in &mut self.frame {
+            row.rotate_left(columns);
+            let width = row.len();
+            row[width - columns..].fill(0);
+        }
+        self.frame_scrolled();
+        self.next_program();
+    }
+
+    // Scroll distances are given in high resolution pixels, so low resolution moves half as far
+    fn scroll_distance(&self, pixels: usize) -> usize { if self.hires { pixels } else { pixels / 2 } }
+
+    fn frame_scrolled(&mut self) {
+        self.screen_dirty = true;
+        if let Some(changes) = &mut self.frame_delta { *changes = delta::full_frame(&self.frame); }
+    }
+
+    // 00FE - LOW
+    fn low(&mut self) {
+        self.set_hires(false);
+        self.next_program();
+    }
+
+    // 00FF - HIGH
+    fn high(&mut self) {
+        self.set_hires(true);
+        self.next_program();
+    }
+
+    // Cxkk - RND Vx, byte
+    fn rnd_vx_byte(&mut self, x: u8, kk: u8) {
+        *self.reg_mut(x) = self.rng.gen::<u8>() & kk;
+        self.next_program();
+    }
+
+    // Dxyn - DRW Vx, Vy, nibble
+    fn drw_vx_vy_nibble(&mut self, x: u8, y: u8, n: u8) {
+        // Vx or Vy may be VF, so the coordinates are read before VF is reset
+        let (mut origin_x, mut origin_y) = (self.reg(x) as usize, self.reg(y) as usize);
+        self.v[0xF] = 0;
+        self.last_collision_count = 0;
+        if self.quirks.origin_wrap {
+            origin_x %= self.width;
+            origin_y %= self.height;
+        }
+        // Dxy0 in high resolution draws a 16x16 sprite, two bytes to a row
+        let (rows, row_bytes) = if n == 0 && self.hires { (16, 2) } else { (n as usize, 1) };
+        let sprite_width = row_bytes * 8;
+        let mut collided_rows = 0;
+        for row in 0..rows {
+            let Some(y) = self.sprite_coord(origin_y, row, self.height) else { continue };
+            let addr = self.i.wrapping_add((row * row_bytes) as u16);
+            let sprite = (0..row_bytes)
+                .fold(0u16, |bits, byte| bits << 8 | self.read_memory(addr.wrapping_add(byte as u16)) as u16);
+            let mut collided = 0;
+            for bit in 0..sprite_width {
+                let Some(x) = self.sprite_coord(origin_x, bit, self.width) else { continue };
+                let pixel = ((sprite >> (sprite_width - 1 - bit)) & 1) as u8;
+                collided |= self.frame[y][x] & pixel;
+                self.last_collision_count += (self.frame[y][x] & pixel) as u32;
+                self.frame[y][x] ^= pixel;
+                if let (1, Some(changes)) = (pixel, &mut self.frame_delta) {
+                    changes.push(FrameChange::Pixel { x, y, value: self.frame[y][x] });
+                }
+                if let Some(intensity) = self.fade.as_mut().and_then(|fade| fade.get_mut(y)?.get_mut(x)) {
+                    *intensity = (*intensity).max(lit_intensity(self.frame[y][x]));
+                }
+            }
+            collided_rows += collided;
+        }
+        self.screen_dirty = true;
+        self.v[0xF] = if self.quirks.collision_row_count { collided_rows } else { collided_rows.min(1) };
+        self.next_program();
+        if self.break_on_collision && collided_rows > 0 {
+            self.break_on_collision = false;
+            self.fault = Some(Fault::Collision);
+        }
+    }
+
+    fn sprite_coord(&self, origin: usize, offset: usize, size: usize) -> Option<usize> {
+        let coord = origin + offset;
+        if coord < size { Some(coord) } else if self.quirks.pixel_wrap { Some(coord % size) } else { None }
+    }
+
+    // Ex9E - SKP Vx
+    fn skp_vx(&mut self, x: u8) {
+        self.next_program();
+        if self.keypad.is_pressed(self.reg(x)) { self.next_program(); }
+    }
+
+    // ExA1 - SKNP Vx
+    fn sknp_vx(&mut self, x: u8) {
+        self.next_program();
+        if !self.keypad.is_pressed(self.reg(x)) { self.next_program(); }
+    }
+
+    // Fx07 - LD Vx, DT
+    fn ld_vx_dt(&mut self, x: u8) {
+        *self.reg_mut(x) = self.dt;
+        self.next_program();
+    }
+
+    // Fx0A - LD Vx, K
+    // Blocks, pc staying put, until a key goes down and back up after the wait started, like the
+    // COSMAC VIP. Keys held from before only count once released and pressed again
+    fn ld_vx_k(&mut self, x: u8) {
+        if !self.fx0a_waiting {
+            self.keypad.reset_transitions();
+            self.fx0a_waiting = true;
+        }
+        let Some(key) = self.keypad.take_press_and_release() else { return };
+        if matches!(self.fx0a_latch, Some((latched, _)) if latched == key) { return; }
+        if self.fx0a_cooldown > 0 { self.fx0a_latch = Some((key, self.fx0a_cooldown)); }
+        self.fx0a_waiting = false;
+        *self.reg_mut(x) = key;
+        self.next_program();
+    }
+
+    // Fx15 - LD DT, Vx
+    fn ld_dt_vx(&mut self, x: u8) {
+        self.dt = self.reg(x);
+        self.next_program();
+    }
+
+    // Fx18 - LD ST, Vx
+    fn ld_st_vx(&mut self, x: u8) {
+        self.st = self.reg(x);
+        self.next_program();
     }
 
     // Fx1E - ADD I, Vx
     fn add_i_vx(&mut self, x: u8) {
-        self.i += self.v[x as usize] as u16;
+        let sum = self.i + self.reg(x) as u16;
+        self.i = sum & 0xFFF;
+        if self.quirks.fx1e_overflow { self.v[0xF] = (sum > 0xFFF) as u8; }
+        self.next_program();
+    }
+
+    // Fx29 - LD F, Vx
+    fn ld_f_vx(&mut self, x: u8) {
+        let digit = self.reg(x) & 0xF;
+        let glyph = if digit < self.font_glyphs {
+            digit
+        } else if self.missing_glyph_box {
+            self.font_glyphs
+        } else {
+            self.font_glyphs - 1
+        };
+        self.i = glyph as u16 * GLYPH_SIZE as u16;
         self.next_program();
     }
 
-    // Fx29 - LD F, Vx
-    fn ld_f_vx(&mut self, x: u8) {
-        self.i = (self.v[x as usize] * 5) as u16;
-        self.next_program();
+    // Fx30 - LD HF, Vx
+    // Digits the big font lacks, all of them when none is loaded, use the small font glyph
+    // rather than pointing I at whatever follows the font
+    fn ld_hf_vx(&mut self, x: u8) {
+        let digit = self.reg(x) & 0xF;
+        if digit >= self.big_font_glyphs {
+            self.ld_f_vx(x);
+            return;
+        }
+        self.i = (ADDR_BIG_FONT + digit as usize * BIG_GLYPH_SIZE) as u16;
+        self.next_program();
+    }
+
+    // Fx33 - LD B, Vx
+    fn ld_b_vx(&mut self, x: u8) {
+        let data = self.reg(x);
+        let mut digits = [data / 100, (data % 100) / 10, data % 10];
+        if self.quirks.bcd_reverse { digits.reverse(); }
+        for (offset, digit) in (0..).zip(digits) { self.write_memory(self.i.wrapping_add(offset), digit); }
+        self.next_program();
+    }
+
+    // Fx55 - LD [I], Vx
+    fn ld_i_vx(&mut self, x: u8) {
+        for j in 0..=(x & 0xF) as u16 {
+            let addr = self.i.wrapping_add(j);
+            self.write_memory(addr, self.v[j as usize]);
+            if let Some(handler) = &mut self.semihosting {
+                if addr & 0xFFF == SEMIHOSTING_ADDR { handler(self.v[j as usize]); }
+            }
+        }
+        self.advance_load_store_i(x);
+        self.next_program();
+    }
+
+    // Fx65 - LD Vx, [I]
+    fn ld_vx_i(&mut self, x: u8) {
+        for j in 0..=(x & 0xF) as u16 { self.v[j as usize] = self.read_memory(self.i.wrapping_add(j)); }
+        self.advance_load_store_i(x);
+        self.next_program();
+    }
+
+    fn advance_load_store_i(&mut self, x: u8) {
+        if self.quirks.load_store_increments_i { self.i = (self.i + (x & 0xF) as u16 + 1) & 0xFFF; }
+    }
+
+    fn reg(&self, x: u8) -> u8 { self.v[(x & 0xF) as usize] }
+
+    fn reg_mut(&mut self, x: u8) -> &mut u8 { &mut self.v[(x & 0xF) as usize] }
+
+    // Addresses wrap around the 4K address space, as does I itself when it's added to
+    fn read_memory(&mut self, addr: u16) -> u8 {
+        let addr = addr & 0xFFF;
+        if let Some(profile) = &mut self.profile { profile.reads[addr as usize] += 1; }
+        self.memory[addr as usize]
+    }
+
+    fn write_memory(&mut self, addr: u16, value: u8) {
+        let addr = addr & 0xFFF;
+        if let Some(profile) = &mut self.profile { profile.writes[addr as usize] += 1; }
+        self.memory[addr as usize] = value;
+        self.max_written_addr = self.max_written_addr.max(addr);
+    }
+
+    fn next_program(&mut self) { self.pc = (self.pc + 2).min(0xFFF); }
+}
+
+// Keypad layout paired with whether each key is down in `keys`
+fn keypad_grid(keys: u16) -> [[(u8, bool); 4]; 4] {
+    KEYPAD_LAYOUT.map(|row| row.map(|key| (key, keys & (1 << key) != 0)))
+}
+
+// Lines of the register panel, V0-V7 and V8-VF side by side followed by the other registers
+fn register_panel(state: &MachineState) -> Vec<String> {
+    let mut lines: Vec<String> = (0..8)
+        .map(|j| format!("V{:X}:{:02X} V{:X}:{:02X}", j, state.v[j], j + 8, state.v[j + 8]))
+        .collect();
+    lines.push(format!("I:{:04X}", state.i));
+    lines.push(format!("PC:{:04X}", state.pc));
+    lines.push(format!("SP:{:02X}", state.sp));
+    lines.push(format!("DT:{:02X}", state.dt));
+    lines.push(format!("ST:{:02X}", state.st));
+    lines
+}
+
+// 64-bit FNV-1a
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01B3))
+}
+
+// Screen positions of the column and row ticks, every RULER_SPACING display pixels
+fn ruler_ticks(width: usize, height: usize, scale: u32) -> (Vec<i32>, Vec<i32>) {
+    let ticks = |size: usize| (RULER_SPACING..size).step_by(RULER_SPACING)
+        .map(|pixel| (pixel as u32 * scale) as i32)
+        .collect();
+    (ticks(width), ticks(height))
+}
+
+fn scale_for_width(display_width: usize, window_width: u32) -> u32 {
+    (window_width / display_width as u32).clamp(MIN_SCALE, MAX_SCALE)
+}
+
+fn screenshot_due(elapsed: Duration, interval: Duration) -> bool { elapsed >= interval }
+
+fn lit_intensity(pixel: u8) -> u8 { if pixel != 0 { u8::MAX } else { 0 } }
+
+fn blend(from: (u8, u8, u8), to: (u8, u8, u8), amount: u8) -> (u8, u8, u8) {
+    let mix = |from: u8, to: u8| (from as i32 + (to as i32 - from as i32) * amount as i32 / 255) as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+// Milliseconds keep two quick presses from overwriting each other
+fn timestamped_path(prefix: &str, extension: &str, time: SystemTime) -> String {
+    let millis = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    format!("{}-{}.{}", prefix, millis, extension)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::tui::frame_to_ascii;
+
+    // Compares the display to rows of # and ., which cover the top left corner with the rest
+    // expected unlit. Leading whitespace is ignored so the pattern can be indented
+    fn assert_frame_ascii(chip: &Chip8, expected: &str) {
+        let rows: Vec<&str> = expected.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+        let expected: Vec<String> = (0..chip.height)
+            .map(|y| format!("{:.<1$}", rows.get(y).unwrap_or(&""), chip.width))
+            .collect();
+        let actual = frame_to_ascii(chip.frame());
+        let actual: Vec<&str> = actual.lines().collect();
+        if expected != actual {
+            let diff: Vec<String> = expected.iter().zip(&actual)
+                .map(|(expected, actual)| format!("{} {} {}", expected, if expected == actual { '|' } else { '!' }, actual))
+                .collect();
+            panic!("Frame differs, expected on the left:\n{}", diff.join("\n"));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Frame differs")]
+    fn test_assert_frame_ascii_mismatch() {
+        let mut chip = Chip8::new_headless();
+        chip.run_op_code(0xF029);
+        chip.run_op_code(0xD005);
+        assert_frame_ascii(&chip, "
+            ####
+            #..#
+            #..#
+            #..#
+            ###.
+        ");
+    }
+
+    #[test]
+    fn test_00e0() {
+        let mut chip = Chip8::new_headless();
+        chip.frame = vec![vec![1; 64]; 32];
+        chip.run_op_code(0x00E0);
+        assert_eq!(chip.frame, [[0; 64]; 32]);
+        assert_eq!(chip.pc, 0x202)
+    }
+
+    #[test]
+    fn test_00ee() {
+        let mut chip = Chip8::new_headless();
+        chip.sp = 2;
+        chip.stack = [0x300; 32];
+        chip.run_op_code(0x00EE);
+        assert_eq!(chip.sp, 1);
+        assert_eq!(chip.pc, 0x300 + 2);
+    }
+
+    #[test]
+    fn test_1nnn() {
+        let mut chip = Chip8::new_headless();
+        chip.run_op_code(0x1444);
+        assert_eq!(chip.pc, 0x444);
+    }
+
+    #[test]
+    fn test_2nnn() {
+        let mut chip = Chip8::new_headless();
+        chip.run_op_code(0x2456);
+
+        assert_eq!(chip.sp, 1);
+        assert_eq!(chip.pc, 0x456);
+        assert_eq!(chip.stack[(chip.sp - 1) as usize], 0x200)
+    }
+
+    #[test]
+    fn test_3xkk() {
+        let mut chip = Chip8::new_headless();
+
+        // Vx == kk
+        chip.v[2] = 0x12;
+        chip.run_op_code(0x3212);
+        assert_eq!(chip.pc, 0x204);
+
+        // Vx != kk
+        chip.v[2] = 0x11;
+        chip.run_op_code(0x3212);
+        assert_eq!(chip.pc, 0x206)
+    }
+
+    #[test]
+    fn test_4xkk() {
+        let mut chip = Chip8::new_headless();
+
+        // Vx != kk
+        chip.v[2] = 0x12;
+        chip.run_op_code(0x4211);
+        assert_eq!(chip.pc, 0x204);
+
+        // Vx == kk
+        chip.v[2] = 0x11;
+        chip.run_op_code(0x4211);
+        assert_eq!(chip.pc, 0x206);
+    }
+
+    #[test]
+    fn test_5xy0() {
+        let mut chip = Chip8::new_headless();
+
+        // Vx == Vy
+        chip.v[2] = 0x2;
+        chip.v[3] = 0x2;
+        chip.run_op_code(0x5230);
+        assert_eq!(chip.pc, 0x204);
+
+        // Vx != Vy
+        chip.v[3] = 0x3;
+        chip.run_op_code(0x5230);
+        assert_eq!(chip.pc, 0x206);
+    }
+
+    #[test]
+    fn test_6xkk() {
+        let mut chip = Chip8::new_headless();
+
+        chip.run_op_code(0x6233);
+        assert_eq!(chip.v[2], 0x33);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_7xkk() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[2] = 0x2;
+        chip.run_op_code(0x7201);
+        assert_eq!(chip.v[2], 0x3);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_8xy0() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0x2;
+        chip.v[2] = 0x3;
+        chip.run_op_code(0x8120);
+        assert_eq!(chip.v[1], 0x3);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_8xy1() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xF0;
+        chip.v[2] = 0x0F;
+        chip.run_op_code(0x8121);
+        assert_eq!(chip.v[1], 0xFF);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_8xy2() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xFF;
+        chip.v[2] = 0x0F;
+        chip.run_op_code(0x8122);
+        assert_eq!(chip.v[1], 0x0F);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_8xy3() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xF0;
+        chip.v[2] = 0xFF;
+        chip.run_op_code(0x8123);
+        assert_eq!(chip.v[1], 0x0F);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_8xy4() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xAA;
+        chip.v[2] = 0xAA;
+        chip.run_op_code(0x8124);
+        assert_eq!(chip.v[1], 0x54);
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.v[1] = 0x11;
+        chip.v[2] = 0x22;
+        chip.run_op_code(0x8124);
+        assert_eq!(chip.v[1], 0x33);
+        assert_eq!(chip.v[0xF], 0);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn test_8xy5() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xFF;
+        chip.v[2] = 0x11;
+        chip.run_op_code(0x8125);
+        assert_eq!(chip.v[1], 0xEE);
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.v[1] = 0x11;
+        chip.v[2] = 0xFF;
+        chip.run_op_code(0x8125);
+        assert_eq!(chip.v[1], 0x12);
+        assert_eq!(chip.v[0xF], 0);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn test_8xy6() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[5] = 14;
+        chip.run_op_code(0x8506);
+        assert_eq!(chip.v[0xF], 0);
+        assert_eq!(chip.v[5], 7);
+    }
+
+    #[test]
+    fn test_shift_uses_vy() {
+        let mut chip = Chip8::new_headless();
+
+        for (shift_uses_vy, shr, shl) in [(false, (0x08, 0), (0x20, 0)), (true, (0x40, 1), (0x02, 1))] {
+            chip.set_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+
+            chip.v[1] = 0x10;
+            chip.v[2] = 0x81;
+            chip.run_op_code(0x8126);
+            assert_eq!((chip.v[1], chip.v[0xF]), shr, "shr, shift_uses_vy: {}", shift_uses_vy);
+            assert_eq!(chip.v[2], 0x81);
+
+            chip.v[1] = 0x10;
+            chip.run_op_code(0x812E);
+            assert_eq!((chip.v[1], chip.v[0xF]), shl, "shl, shift_uses_vy: {}", shift_uses_vy);
+        }
+    }
+
+    #[test]
+    fn test_shift_into_vf() {
+        let mut chip = Chip8::new_headless();
+
+        for shift_uses_vy in [false, true] {
+            chip.set_quirks(Quirks { shift_uses_vy, ..Quirks::default() });
+            let source = if shift_uses_vy { 1 } else { 0xF };
+
+            chip.v[0xF] = 0x81;
+            chip.v[1] = 0x81;
+            chip.run_op_code(0x8F06 | source << 4);
+            assert_eq!(chip.v[0xF], 1, "shr, shift_uses_vy: {}", shift_uses_vy);
+
+            chip.v[0xF] = 0x7E;
+            chip.v[1] = 0x7E;
+            chip.run_op_code(0x8F0E | source << 4);
+            assert_eq!(chip.v[0xF], 0, "shl, shift_uses_vy: {}", shift_uses_vy);
+
+            chip.v[0xF] = 0x80;
+            chip.v[1] = 0x80;
+            chip.run_op_code(0x8F0E | source << 4);
+            assert_eq!(chip.v[0xF], 1, "shl, shift_uses_vy: {}", shift_uses_vy);
+        }
+
+        chip.v[1] = 0x0C;
+        chip.v[2] = 0x03;
+        chip.run_op_code(0x8126);
+        assert_eq!((chip.v[1], chip.v[0xF]), (0x01, 1));
+    }
+
+    #[test]
+    fn test_8xy7() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0x1;
+        chip.v[2] = 0x2;
+        chip.run_op_code(0x8127);
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.v[1], 0x1);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.v[1] = 0x2;
+        chip.v[2] = 0x1;
+        chip.run_op_code(0x8127);
+        assert_eq!(chip.v[0xF], 0);
+        assert_eq!(chip.v[1], 0xFF);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn test_8xye() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xAA;
+        chip.run_op_code(0x810E);
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.v[1], 0x54);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.run_op_code(0x810E);
+        assert_eq!(chip.v[0xF], 0);
+        assert_eq!(chip.v[1], 0xA8);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn test_9xy0() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 1;
+        chip.v[2] = 2;
+        chip.run_op_code(0x9120);
+        assert_eq!(chip.pc, 0x204);
+
+        chip.v[1] = 2;
+        chip.run_op_code(0x9120);
+        assert_eq!(chip.pc, 0x206);
+    }
+
+    #[test]
+    fn test_annn() {
+        let mut chip = Chip8::new_headless();
+
+        chip.run_op_code(0xA123);
+        assert_eq!(chip.i, 0x123);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_bnnn() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[0] = 2;
+        chip.v[1] = 5;
+        chip.run_op_code(0xB123);
+        assert_eq!(chip.pc, 0x125);
+
+        chip.set_quirks(Quirks { jump_uses_vx: true, ..Quirks::default() });
+        chip.run_op_code(0xB123);
+        assert_eq!(chip.pc, 0x128);
+    }
+
+    #[test]
+    fn test_cxkk() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 1;
+        chip.run_op_code(0xC1AA);
+        assert_ne!(chip.v[1], 1);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_dxyn() {
+        let mut chip = Chip8::new_headless();
+
+        chip.i = 0x400;
+        chip.v[0] = 2;
+        chip.v[1] = 1;
+        chip.memory[0x400] = 0b11101010;
+        chip.memory[0x401] = 0b10101100;
+        chip.memory[0x402] = 0b10101010;
+        chip.memory[0x403] = 0b11101001;
+        chip.run_op_code(0xD014);
+        assert_eq!(chip.frame[1][2..10], [1, 1, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(chip.frame[2][2..10], [1, 0, 1, 0, 1, 1, 0, 0]);
+        assert_eq!(chip.frame[3][2..10], [1, 0, 1, 0, 1, 0, 1, 0]);
+        assert_eq!(chip.frame[4][2..10], [1, 1, 1, 0, 1, 0, 0, 1]);
+        assert_frame_ascii(&chip, "
+            ..........
+            ..###.#.#.
+            ..#.#.##..
+            ..#.#.#.#.
+            ..###.#..#
+        ");
+        assert_eq!(chip.v[0xF], 0);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.run_op_code(0xD004);
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn test_dxyn_wrap_quirks() {
+        let mut chip = Chip8::new_headless();
+        chip.i = 0x400;
+        chip.memory[0x400] = 0xFF;
+        chip.v[0] = 126;
+
+        let cases = [
+            (true, true, [0, 1, 2, 3, 4, 5, 62, 63].as_slice()),
+            (true, false, [62, 63].as_slice()),
+            (false, true, [0, 1, 2, 3, 4, 5, 62, 63].as_slice()),
+            (false, false, [].as_slice()),
+        ];
+        for (origin_wrap, pixel_wrap, lit) in cases {
+            chip.set_quirks(Quirks { origin_wrap, pixel_wrap, ..Quirks::default() });
+            chip.frame = vec![vec![0; 64]; 32];
+            chip.run_op_code(0xD011);
+            let drawn: Vec<usize> = (0..64).filter(|&x| chip.frame[0][x] == 1).collect();
+            assert_eq!(drawn, lit, "origin_wrap: {}, pixel_wrap: {}", origin_wrap, pixel_wrap);
+        }
+    }
+
+    #[test]
+    fn test_clear_display() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 8 / LD F, V0 / DRW V0, V0, 5
+        chip.memory[0x200..0x206].copy_from_slice(&[0x60, 0x08, 0xF0, 0x29, 0xD0, 0x05]);
+        for _ in 0..3 { chip.step(); }
+        assert!(chip.frame.iter().flatten().any(|&pixel| pixel == 1));
+        let state = chip.state();
+
+        chip.clear_display();
+        assert_eq!(chip.frame, [[0; 64]; 32]);
+        assert_eq!(chip.state(), state);
+    }
+
+    #[test]
+    fn test_set_platform() {
+        let mut chip = Chip8::headless_with_display(64, 48);
+        chip.load_rom_bytes(&[0x60, 0x01]).unwrap();
+        chip.set_platform(Platform::Schip);
+        assert_eq!(chip.platform(), Platform::Schip);
+        assert_eq!(chip.quirks(), Quirks::schip());
+        assert_eq!((chip.width, chip.height), (64, 32));
+        assert_eq!(chip.frame().len(), 32);
+        assert_eq!(chip.rom(), [0x60, 0x01]);
+
+        chip.step();
+        chip.set_platform(Platform::Chip8);
+        assert_eq!(chip.quirks(), Quirks::default());
+        assert_eq!((chip.pc, chip.v[0]), (0x200, 0));
+    }
+
+    #[test]
+    fn test_custom_display() {
+        let mut chip = Chip8::headless_with_display(64, 48);
+        chip.i = 0x400;
+        chip.memory[0x400..0x402].copy_from_slice(&[0xC0, 0xC0]);
+        chip.v[0] = 63;
+        chip.v[1] = 47;
+
+        chip.run_op_code(0xD012);
+        assert_eq!(chip.frame.len(), 48);
+        let lit: Vec<(usize, usize)> = (0..48)
+            .flat_map(|y| (0..64).map(move |x| (x, y)))
+            .filter(|&(x, y)| chip.frame[y][x] == 1)
+            .collect();
+        assert_eq!(lit, [(0, 0), (63, 0), (0, 47), (63, 47)]);
+
+        chip.v[1] = 79;
+        chip.run_op_code(0xD011);
+        assert_eq!(chip.frame[31][63], 1);
+        assert_eq!(chip.window_size(), (640, 480));
+    }
+
+    #[test]
+    fn test_display_wait() {
+        let mut chip = Chip8::new_headless();
+        chip.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+        // DRW V0, V0, 1 / ADD V1, 1 / JP 0x200
+        chip.memory[0x200..0x206].copy_from_slice(&[0xD0, 0x01, 0x71, 0x01, 0x12, 0x00]);
+
+        assert!(chip.step());
+        assert_eq!(chip.pc, 0x202);
+        assert!(!chip.step());
+        assert!(!chip.step());
+        assert_eq!(chip.pc, 0x200);
+
+        chip.step_frame();
+        assert_eq!(chip.pc, 0x202);
+        assert_eq!(chip.v[1], 1);
+    }
+
+    #[test]
+    fn test_dxyn_vf_coordinate() {
+        let mut chip = Chip8::new_headless();
+        chip.i = 0x400;
+        chip.memory[0x400] = 0x80;
+        chip.v[0xF] = 10;
+        chip.v[0] = 3;
+
+        chip.run_op_code(0xDF01);
+        assert_eq!(chip.frame[3][10], 1);
+        assert_eq!(chip.frame[3][0], 0);
+        assert_eq!(chip.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_dxyn_collision_row_count() {
+        let mut chip = Chip8::new_headless();
+        chip.i = 0x400;
+        chip.memory[0x400..0x404].copy_from_slice(&[0x80, 0x00, 0x81, 0xC0]);
+
+        for (quirks, vf) in [(Quirks::default(), 1), (Quirks::schip(), 3)] {
+            chip.set_quirks(quirks);
+            chip.frame = vec![vec![0; 64]; 32];
+            chip.run_op_code(0xD014);
+            chip.run_op_code(0xD014);
+            assert_eq!(chip.v[0xF], vf, "{:?}", quirks);
+            assert_eq!(chip.last_collision_count(), 5);
+        }
+    }
+
+    #[test]
+    fn test_ex9e() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 1;
+        chip.keypad.down_key(Keycode::Num1);
+        chip.run_op_code(0xE19E);
+        assert_eq!(chip.pc, 0x204);
+
+        chip.keypad.clear_keys();
+        chip.run_op_code(0xE19E);
+        assert_eq!(chip.pc, 0x206);
+    }
+
+    #[test]
+    fn test_exa1() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 1;
+        chip.run_op_code(0xE1A1);
+        assert_eq!(chip.pc, 0x204);
+
+        chip.keypad.down_key(Keycode::Num1);
+        chip.run_op_code(0xE1A1);
+        assert_eq!(chip.pc, 0x206);
+    }
+
+    #[test]
+    fn test_fx07() {
+        let mut chip = Chip8::new_headless();
+
+        chip.dt = 2;
+        chip.run_op_code(0xF107);
+        assert_eq!(chip.v[1], 2);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_fx0a() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 0xFF;
+        chip.run_op_code(0xF10A);
+        chip.keypad.down_key(Keycode::Num1);
+        // Held down over several cycles
+        for _ in 0..3 {
+            chip.run_op_code(0xF10A);
+            assert_eq!((chip.v[1], chip.pc), (0xFF, 0x200));
+        }
+
+        chip.keypad.release_key(Keycode::Num1);
+        chip.run_op_code(0xF10A);
+        assert_eq!(chip.v[1], 1);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_fx0a_cooldown() {
+        let mut chip = Chip8::new_headless();
+        chip.set_fx0a_cooldown(2);
+
+        chip.run_op_code(0xF10A);
+        chip.keypad.down_key(Keycode::Num1);
+        chip.keypad.release_key(Keycode::Num1);
+        chip.run_op_code(0xF10A);
+        assert_eq!(chip.pc, 0x202);
+
+        // Quick re-press of the same key within the cooldown
+        chip.tick_timers();
+        chip.run_op_code(0xF20A);
+        chip.keypad.down_key(Keycode::Num1);
+        chip.keypad.release_key(Keycode::Num1);
+        chip.run_op_code(0xF20A);
+        assert_eq!(chip.v[2], 0);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.tick_timers();
+        chip.keypad.down_key(Keycode::Num1);
+        chip.keypad.release_key(Keycode::Num1);
+        chip.run_op_code(0xF20A);
+        assert_eq!(chip.v[2], 1);
+        assert_eq!(chip.pc, 0x204);
     }
 
-    // Fx33 - LD B, Vx
-    fn ld_b_vx(&mut self, x: u8) {
-        let data = self.v[x as usize];
-        self.memory[self.i as usize] = data / 100;
-        self.memory[(self.i + 1) as usize] = (data % 100) / 10;
-        self.memory[(self.i + 2) as usize] = data % 10;
-        self.next_program();
+    #[test]
+    fn test_fx15() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 3;
+        chip.run_op_code(0xF115);
+        assert_eq!(chip.dt, 3);
+        assert_eq!(chip.pc, 0x202);
     }
 
-    // Fx55 - LD [I], Vx
-    fn ld_i_vx(&mut self, x: u8) {
-        for j in 0..=x as u16 { self.memory[(self.i + j) as usize] = self.v[j as usize]; }
-        self.next_program();
+    #[test]
+    fn test_fx18() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 3;
+        chip.run_op_code(0xF118);
+        assert_eq!(chip.st, 3);
+        assert_eq!(chip.pc, 0x202);
     }
 
-    // Fx65 - LD Vx, [I]
-    fn ld_vx_i(&mut self, x: u8) {
-        for j in 0..=x as u16 { self.v[j as usize] = self.memory[(self.i + j) as usize]; }
-        self.next_program();
+    #[test]
+    fn test_fx1e() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 2;
+        chip.run_op_code(0xF11E);
+        assert_eq!(chip.i, 0x202);
+        assert_eq!(chip.pc, 0x202);
+
+        chip.i = 0xFFE;
+        chip.v[1] = 4;
+        chip.v[0xF] = 7;
+        chip.run_op_code(0xF11E);
+        assert_eq!((chip.i, chip.v[0xF]), (0x002, 7));
     }
 
-    fn next_program(&mut self) { self.pc = (self.pc + 2).min(0xFFF); }
-}
+    #[test]
+    fn test_fx1e_overflow() {
+        let mut chip = Chip8::new_headless();
+        chip.set_quirks(Quirks { fx1e_overflow: true, ..Quirks::default() });
+
+        chip.i = 0xFFE;
+        chip.v[1] = 1;
+        chip.run_op_code(0xF11E);
+        assert_eq!((chip.i, chip.v[0xF]), (0xFFF, 0));
+
+        chip.run_op_code(0xF11E);
+        assert_eq!((chip.i, chip.v[0xF]), (0x000, 1));
+
+        chip.i = 0x200;
+        chip.run_op_code(0xF11E);
+        assert_eq!((chip.i, chip.v[0xF]), (0x201, 0));
+
+        // VF as the operand is read before it's overwritten
+        chip.i = 0xFFF;
+        chip.v[0xF] = 1;
+        chip.run_op_code(0xFF1E);
+        assert_eq!((chip.i, chip.v[0xF]), (0x000, 1));
+    }
 
-// cargo test -- --test-threads=1
-#[cfg(test)]
-mod tests {
-    use super::*;
-    
     #[test]
-    fn test_00e0() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-        chip.frame = [[1; 64]; 32];
-        chip.run_op_code(0x00E0);
-        assert_eq!(chip.frame, [[0; 64]; 32]);
-        assert_eq!(chip.pc, 0x202)
+    fn test_top_of_memory() {
+        let mut chip = Chip8::new_headless();
+        chip.memory[0xFFE..].copy_from_slice(&[0x80, 0x40]);
+
+        // The third sprite row comes from 0x000, the top of the 0 glyph
+        chip.i = 0xFFE;
+        chip.run_op_code(0xD003);
+        assert_frame_ascii(&chip, "
+            #.......
+            .#......
+            ####....
+        ");
+
+        chip.v[0..3].copy_from_slice(&[1, 2, 3]);
+        chip.run_op_code(0xF255);
+        assert_eq!((chip.memory[0xFFE], chip.memory[0xFFF], chip.memory[0x000]), (1, 2, 3));
+
+        chip.v[0] = 123;
+        chip.i = 0xFFF;
+        chip.run_op_code(0xF033);
+        assert_eq!((chip.memory[0xFFF], chip.memory[0x000], chip.memory[0x001]), (1, 2, 3));
+
+        chip.set_quirks(Quirks { load_store_increments_i: true, ..Quirks::default() });
+        chip.run_op_code(0xF165);
+        assert_eq!((chip.v[0], chip.v[1], chip.i), (1, 2, 0x001));
     }
 
     #[test]
-    fn test_00ee() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-        chip.sp = 2;
-        chip.stack = [3; 32];
-        chip.run_op_code(0x00EE);
-        assert_eq!(chip.sp, 1);
-        assert_eq!(chip.pc, 3 + 2);
+    fn test_fx29() {
+        let mut chip = Chip8::new_headless();
+
+        chip.v[1] = 1;
+        chip.run_op_code(0xF129);
+        assert_eq!(chip.i, 5);
+        assert_eq!(chip.pc, 0x202);
+
+        // Only the low nibble picks the digit
+        chip.v[1] = 0xFF;
+        chip.run_op_code(0xF129);
+        assert_eq!(chip.i, 0xF * 5);
     }
 
     #[test]
-    fn test_1nnn() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-        chip.run_op_code(0x1444);
-        assert_eq!(chip.pc, 0x444);
+    fn test_fx29_short_font() {
+        let mut chip = Chip8::new_headless();
+        chip.load_font(&FONT_SET[..50]);
+        chip.v[1] = 0xF;
+
+        chip.run_op_code(0xF129);
+        assert_eq!(chip.i, 45);
+
+        chip.set_missing_glyph_box(true);
+        chip.run_op_code(0xF129);
+        assert_eq!(chip.i, 50);
+        assert_eq!(chip.memory[50..55], MISSING_GLYPH);
+        assert_eq!(chip.memory[55..80], [0; 25]);
+
+        chip.v[1] = 9;
+        chip.run_op_code(0xF129);
+        assert_eq!(chip.i, 45);
     }
 
     #[test]
-    fn test_2nnn() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-        chip.run_op_code(0x2456);
+    fn test_fx30() {
+        let mut chip = Chip8::new_headless();
+        chip.v[1] = 2;
+        chip.run_op_code(0xF130);
+        assert_eq!(chip.i, 10);
+        assert_eq!(chip.pc, 0x202);
 
-        assert_eq!(chip.sp, 1);
-        assert_eq!(chip.pc, 0x456);
-        assert_eq!(chip.stack[(chip.sp - 1) as usize], 0x200)
+        chip.load_big_font(&[0xFF; 30]);
+        chip.run_op_code(0xF130);
+        assert_eq!(chip.i, 0x50 + 20);
+        chip.v[1] = 3;
+        chip.run_op_code(0xF130);
+        assert_eq!(chip.i, 15);
+
+        chip.reset();
+        assert_eq!(chip.memory[0x50..0x6E], [0xFF; 30]);
     }
 
     #[test]
-    fn test_3xkk() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_fx33() {
+        let mut chip = Chip8::new_headless();
 
-        // Vx == kk
-        chip.v[2] = 0x12;
-        chip.run_op_code(0x3212);
-        assert_eq!(chip.pc, 0x204);
+        chip.v[1] = 123;
+        chip.run_op_code(0xF133);
+        assert_eq!(chip.memory[chip.i as usize], 1);
+        assert_eq!(chip.memory[chip.i as usize + 1], 2);
+        assert_eq!(chip.memory[chip.i as usize + 2], 3);
+        assert_eq!(chip.pc, 0x202);
 
-        // Vx != kk
-        chip.v[2] = 0x11;
-        chip.run_op_code(0x3212);
-        assert_eq!(chip.pc, 0x206)
+        chip.set_quirks(Quirks { bcd_reverse: true, ..Quirks::default() });
+        chip.run_op_code(0xF133);
+        assert_eq!(chip.memory[chip.i as usize..chip.i as usize + 3], [3, 2, 1]);
+    }
+
+    #[test]
+    fn test_fx55() {
+        let mut chip = Chip8::new_headless();
+        let i = chip.i as usize;
+
+        chip.v[0] = 0;
+        chip.v[1] = 1;
+        chip.v[2] = 2;
+        chip.run_op_code(0xF255);
+        assert_eq!(chip.memory[i], 0);
+        assert_eq!(chip.memory[i + 1], 1);
+        assert_eq!(chip.memory[i + 2], 2);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_load_store_increments_i() {
+        let mut chip = Chip8::new_headless();
+
+        for (load_store_increments_i, i) in [(false, 0x300), (true, 0x304)] {
+            chip.set_quirks(Quirks { load_store_increments_i, ..Quirks::default() });
+            chip.i = 0x300;
+            chip.run_op_code(0xF355);
+            assert_eq!(chip.i, i, "Fx55, load_store_increments_i: {}", load_store_increments_i);
+
+            chip.i = 0x300;
+            chip.run_op_code(0xF365);
+            assert_eq!(chip.i, i, "Fx65, load_store_increments_i: {}", load_store_increments_i);
+        }
+    }
+
+    #[test]
+    fn test_semihosting() {
+        let mut chip = Chip8::new_headless();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&received);
+        chip.set_semihosting(move |value| sink.borrow_mut().push(value));
+
+        // LD V0, 0x2A / LD V1, 0x01 / LD I, 0xFFE / LD [I], V1 / LD I, 0xFFF / LD [I], V0
+        chip.memory[0x200..0x20C].copy_from_slice(&[0x60, 0x2A, 0x61, 0x01, 0xAF, 0xFE, 0xF1, 0x55, 0xAF, 0xFF, 0xF0, 0x55]);
+        for _ in 0..6 { chip.step(); }
+        assert_eq!(*received.borrow(), [0x01, 0x2A]);
+    }
+
+    #[test]
+    fn test_on_timer_tick() {
+        let mut chip = Chip8::new_headless();
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let sink = Rc::clone(&received);
+        chip.set_on_timer_tick(move |dt, st| sink.borrow_mut().push((dt, st)));
+
+        chip.dt = 3;
+        chip.st = 1;
+        for _ in 0..4 { chip.tick_timers(); }
+        assert_eq!(*received.borrow(), [(2, 0), (1, 0), (0, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn test_max_written_addr() {
+        let mut chip = Chip8::new_headless();
+        assert_eq!(chip.max_written_addr(), 0);
+
+        chip.i = 0xFFD;
+        chip.run_op_code(0xF255);
+        assert_eq!(chip.max_written_addr(), 0xFFF);
+
+        chip.i = 0x300;
+        chip.run_op_code(0xF255);
+        assert_eq!(chip.max_written_addr(), 0xFFF);
+    }
+
+    #[test]
+    fn test_fx65() {
+        let mut chip = Chip8::new_headless();
+
+        chip.memory[chip.i as usize] = 0;
+        chip.memory[chip.i as usize + 1] = 1;
+        chip.memory[chip.i as usize + 2] = 2;
+        chip.run_op_code(0xF265);
+        assert_eq!(chip.v[0], 0);
+        assert_eq!(chip.v[1], 1);
+        assert_eq!(chip.v[2], 2);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_run_script() {
+        let mut chip = Chip8::new_headless();
+
+        // LD V1, 5 / SKP V1 / JP 0x202 / JP 0x206
+        chip.memory[0x200..0x208].copy_from_slice(&[0x61, 0x05, 0xE1, 0x9E, 0x12, 0x02, 0x12, 0x06]);
+        let script = [(5, 1 << 5), (6, 0)];
+
+        chip.run_script(&script, 5);
+        assert_eq!(chip.frame_count, 5);
+        assert!(!chip.keypad.is_pressed(5));
+        assert_ne!(chip.pc, 0x206);
+
+        chip.run_script(&script, 1);
+        assert!(chip.keypad.is_pressed(5));
+        assert_eq!(chip.pc, 0x206);
+
+        chip.run_script(&script, 1);
+        assert!(!chip.keypad.is_pressed(5));
+        assert_eq!(chip.frame_count, 7);
+    }
+
+    #[test]
+    fn test_sound_timeline() {
+        let mut chip = Chip8::new_headless();
+        chip.enable_audio_recording();
+        // LD V0, 2 / LD ST, V0 / JP 0x204
+        chip.memory[0x200..0x206].copy_from_slice(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]);
+
+        for _ in 0..4 { chip.step_frame(); }
+        assert_eq!(chip.sound_timeline.as_deref(), Some([true, true, false, false].as_slice()));
+
+        // Muting doesn't silence the recording
+        chip.reset();
+        chip.set_muted(true);
+        chip.enable_audio_recording();
+        chip.memory[0x200..0x206].copy_from_slice(&[0x60, 0x02, 0xF0, 0x18, 0x12, 0x04]);
+        for _ in 0..4 { chip.step_frame(); }
+        assert!(!chip.is_beeping());
+        assert_eq!(chip.sound_timeline.as_deref(), Some([true, true, false, false].as_slice()));
+    }
+
+    #[test]
+    fn test_is_beeping() {
+        let mut chip = Chip8::new_headless();
+        assert!(!chip.is_beeping());
+
+        chip.st = 1;
+        assert!(chip.is_beeping());
+        chip.set_muted(true);
+        assert!(!chip.is_beeping());
+        chip.set_muted(false);
+
+        chip.set_min_sound_timer(2);
+        assert!(!chip.is_beeping());
+        chip.st = 2;
+        assert!(chip.is_beeping());
+    }
+
+    #[test]
+    fn test_timers_independent_of_ips() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 0xFF / LD DT, V0 / JP 0x204
+        chip.memory[0x200..0x206].copy_from_slice(&[0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04]);
+        chip.step();
+        chip.step();
+
+        let mut timestep = FixedTimestep::new(timing::step_interval(TIMER_HZ));
+        let mut cpu_timestep = FixedTimestep::new(timing::step_interval(100_000));
+        cpu_timestep.set_max_steps(u32::MAX);
+        // 50ms fits 5000 instructions but only 3 timer ticks
+        chip.advance_clocks(Duration::from_millis(50), &mut timestep, Some(&mut cpu_timestep));
+        assert_eq!(chip.instruction_count, 5002);
+        assert_eq!(chip.dt, 0xFF - 3);
+    }
+
+    #[test]
+    fn test_display_wait_at_ips() {
+        let mut chip = Chip8::new_headless();
+        chip.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+        // DRW V0, V0, 1 / ADD V1, 1 / JP 0x200
+        chip.memory[0x200..0x206].copy_from_slice(&[0xD0, 0x01, 0x71, 0x01, 0x12, 0x00]);
+
+        let frame = timing::step_interval(TIMER_HZ);
+        let mut timestep = FixedTimestep::new(frame);
+        let mut cpu_timestep = FixedTimestep::new(timing::step_interval(100_000));
+        cpu_timestep.set_max_steps(u32::MAX);
+        // Each frame runs up to and including one draw, however many instructions fit
+        for count in [1, 4, 7] {
+            chip.advance_clocks(frame, &mut timestep, Some(&mut cpu_timestep));
+            assert_eq!(chip.instruction_count, count);
+        }
+        assert_eq!(chip.v[1], 2);
+    }
+
+    #[test]
+    fn test_host_driven() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 3 / LD DT, V0 / LD V1, DT / SE V1, 0 / JP 0x204 / JP 0x20A
+        chip.memory[0x200..0x20C].copy_from_slice(&[0x60, 0x03, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x12, 0x0A]);
+
+        for _ in 0..2 { chip.step(); }
+        assert_eq!(chip.dt, 3);
+        for _ in 0..30 { chip.step(); }
+        assert_eq!(chip.dt, 3);
+        assert_ne!(chip.pc, 0x20A);
+
+        for _ in 0..3 {
+            chip.tick_timers();
+            for _ in 0..3 { chip.step(); }
+        }
+        assert_eq!(chip.dt, 0);
+        assert_eq!(chip.pc, 0x20A);
+        assert_eq!(chip.frame_count, 0);
+    }
+
+    #[test]
+    fn test_step_frame_back() {
+        let mut chip = Chip8::new_headless();
+        chip.enable_rewind();
+        // ADD V0, 1 / LD F, V0 / DRW V1, V1, 5 / LD DT, V0 / LD [I], V0 / JP 0x200
+        chip.memory[0x200..0x20C]
+            .copy_from_slice(&[0x70, 0x01, 0xF0, 0x29, 0xD1, 0x15, 0xF0, 0x15, 0xF0, 0x55, 0x12, 0x00]);
+
+        let mut states = Vec::new();
+        for _ in 0..5 {
+            chip.step_frame();
+            states.push((chip.state(), chip.frame.clone(), chip.memory, chip.frame_count));
+        }
+
+        assert!(chip.step_frame_back());
+        assert!(chip.step_frame_back());
+        assert_eq!((chip.state(), chip.frame.clone(), chip.memory, chip.frame_count), states[2]);
+
+        for _ in 0..3 { chip.step_frame_back(); }
+        assert_eq!(chip.state().pc, 0x200);
+        assert!(!chip.step_frame_back());
+    }
+
+    #[test]
+    fn test_run_unthrottled() {
+        let mut chip = Chip8::new_headless();
+        // ADD V0, 1 / JP 0x200
+        chip.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+
+        chip.run_unthrottled(3);
+        let metrics = chip.metrics();
+        assert_eq!(metrics.frames, 3);
+        assert_eq!(metrics.instructions, 3 * CYCLES_PER_FRAME as u64);
+        assert_eq!(chip.v[0], 15);
+    }
+
+    #[test]
+    fn test_run_until_input() {
+        let mut chip = Chip8::new_headless();
+        // ADD V0, 1 / JP 0x200
+        chip.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+
+        let mut polls = 0;
+        assert!(chip.run_until_input(100, || { polls += 1; if polls > 8 { 1 << 4 } else { 0 } }));
+        assert_eq!(chip.v[0], 4);
+        assert!(chip.keypad.is_pressed(4));
+
+        // A key held from the start has to be released first
+        let mut polls = 0;
+        assert!(chip.run_until_input(100, || { polls += 1; if polls == 5 { 0 } else { 1 } }));
+        assert_eq!(chip.v[0], 7);
+
+        assert!(!chip.run_until_input(10, || 0));
+        assert_eq!(chip.v[0], 12);
+    }
+
+    #[test]
+    fn test_random_op_codes() {
+        let mut chip = Chip8::new_headless();
+
+        chip.ld_vx_byte(0x13, 7);
+        assert_eq!(chip.v[3], 7);
+        assert_eq!(chip.reg(0xF3), 7);
+
+        // Seeded so a failure reproduces, the failing op code is in the message
+        let mut rng = StdRng::seed_from_u64(0xC8);
+        for _ in 0..100_000 {
+            chip.fault = None;
+            let op_code = rng.gen::<u16>();
+            let ran = panic::catch_unwind(AssertUnwindSafe(|| chip.run_op_code(op_code)));
+            assert!(ran.is_ok(), "{:04X} panicked", op_code);
+            assert!(chip.pc <= 0xFFF, "{:04X} left pc at {:#X}", op_code, chip.pc);
+            assert!(chip.sp as usize <= chip.stack.len(), "{:04X} left sp at {}", op_code, chip.sp);
+        }
+    }
+
+    #[test]
+    fn test_step() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 3 / LD DT, V0 / ADD V1, 2 / JP 0x204
+        chip.load_rom_bytes(&[0x60, 0x03, 0xF0, 0x15, 0x71, 0x02, 0x12, 0x04]).unwrap();
+
+        let expected_pcs = [0x202, 0x204, 0x206, 0x204, 0x206, 0x204];
+        for (n, &pc) in expected_pcs.iter().enumerate() {
+            assert!(!chip.step());
+            assert_eq!(chip.pc, pc, "step {}", n + 1);
+        }
+        assert_eq!((chip.v[0], chip.v[1]), (3, 4));
+        // Steps never tick the timers, only frames do
+        assert_eq!(chip.dt, 3);
+        assert_eq!(chip.metrics().instructions, 6);
+    }
+
+    #[test]
+    fn test_legacy_timer_mode() {
+        let mut chip = Chip8::new_headless();
+        chip.set_legacy_timer_mode(true);
+        // LD V0, 20 / LD DT, V0 / JP 0x204
+        chip.load_rom_bytes(&[0x60, 0x14, 0xF0, 0x15, 0x12, 0x04]).unwrap();
+        chip.step();
+        chip.step();
+        assert_eq!(chip.dt, 19);
+        chip.step();
+        assert_eq!(chip.dt, 18);
+
+        // Frames add no ticks of their own
+        chip.step_frame();
+        assert_eq!(chip.dt, 18 - CYCLES_PER_FRAME as u8);
+    }
+
+    #[test]
+    fn test_redraw_only_when_dirty() {
+        let mut chip = Chip8::new_headless();
+        let pixels = (FRAME_WIDTH * FRAME_HEIGHT) as u64;
+        // LD V0, 1 / LD F, V0 / DRW V0, V0, 5 / CLS
+        chip.load_rom_bytes(&[0x60, 0x01, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xE0]).unwrap();
+        chip.update_screen();
+        let fill_rects = chip.fill_rects;
+
+        chip.step();
+        chip.step();
+        assert!(!chip.update_screen());
+        assert_eq!(chip.fill_rects, fill_rects);
+
+        chip.step();
+        assert!(chip.update_screen());
+        assert!(!chip.update_screen());
+        assert_eq!(chip.fill_rects, fill_rects + pixels);
+
+        chip.step();
+        chip.update_screen();
+        assert_eq!(chip.fill_rects, fill_rects + 2 * pixels);
+
+        chip.present();
+        assert_eq!(chip.fill_rects, fill_rects + 3 * pixels);
+        chip.set_debug_panel(true);
+        chip.update_screen();
+        chip.update_screen();
+        assert_eq!(chip.fill_rects, fill_rects + 5 * pixels);
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut chip = Chip8::new_headless();
+        chip.enable_rewind();
+        chip.v[0] = 66;
+        chip.v[1] = 1;
+        chip.i = 0x300;
+        chip.memory[0x300] = 0b1100_0000;
+        chip.run_op_code(0xD011);
+        assert_eq!(chip.frame[1][2..4], [1, 1]);
+
+        chip.step_frame();
+        chip.run_op_code(0x00FF);
+        assert!(chip.hires());
+        assert_eq!((chip.width, chip.height), (128, 64));
+        assert_eq!(chip.window_size(), (1280, 640));
+        assert!(chip.frame.iter().flatten().all(|&pixel| pixel == 0));
+
+        // 130 wraps to 2 in 128 columns, and the sprite runs past the right edge
+        chip.v[0] = 130;
+        chip.run_op_code(0xD011);
+        assert_eq!(chip.frame[1][2..4], [1, 1]);
+        chip.v[0] = 127;
+        chip.run_op_code(0xD011);
+        assert_eq!((chip.frame[1][127], chip.frame[1][0]), (1, 1));
+
+        chip.run_op_code(0x00FE);
+        assert_eq!((chip.width, chip.height), (64, 32));
+        assert_eq!(chip.frame.len(), 32);
+
+        chip.run_op_code(0x00FF);
+        assert!(chip.step_frame_back());
+        assert!(!chip.hires());
+        assert_eq!((chip.width, chip.height), (64, 32));
+        assert_eq!(chip.frame[1][2..4], [1, 1]);
+
+        chip.run_op_code(0x00FF);
+        chip.reset();
+        assert_eq!((chip.hires(), chip.frame[0].len()), (false, 64));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut chip = Chip8::new_headless();
+        chip.load_rom_bytes(&[0x62, 0x33, 0xA2, 0xF0, 0xD0, 0x15, 0x00, 0xFF, 0xFF, 0xFF]).unwrap();
+        assert_eq!(chip.disassemble(ADDR_PROGRAM_START, 5), [
+            (0x200, String::from("LD V2, 0x33")),
+            (0x202, String::from("LD I, 0x2F0")),
+            (0x204, String::from("DRW V0, V1, 0x5")),
+            (0x206, String::from("HIGH")),
+            (0x208, String::from("DB 0xFFFF")),
+        ]);
+        chip.memory[0xFFE..].copy_from_slice(&[0x00, 0xE0]);
+        assert_eq!(chip.disassemble(0xFFE, 2)[0], (0xFFE, String::from("CLS")));
+        assert_eq!(chip.disassemble(0xFFE, 2)[1].0, 0x000);
+        assert!(chip.disassemble(0x200, 0).is_empty());
+    }
+
+    #[test]
+    fn test_save_state() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 8 / LD F, V0 / CALL 0x208 / - / DRW V0, V0, 5 / LD DT, V0
+        chip.load_rom_bytes(&[0x60, 0x08, 0xF0, 0x29, 0x22, 0x08, 0x00, 0x00, 0xD0, 0x05, 0xF0, 0x15]).unwrap();
+        for _ in 0..5 { chip.step(); }
+        chip.run_op_code(0x00FF);
+        chip.run_op_code(0xD005);
+        let state = chip.save_state();
+        let (v, i, stack, sp, dt, pc, memory, frame) =
+            (chip.v, chip.i, chip.stack, chip.sp, chip.dt, chip.pc, chip.memory, chip.frame.clone());
+
+        chip.reset();
+        chip.v[3] = 0x33;
+        chip.memory[0x300] = 0xFF;
+        chip.load_state(&state).unwrap();
+        assert_eq!((chip.v, chip.i, chip.stack, chip.sp, chip.dt, chip.pc), (v, i, stack, sp, dt, pc));
+        assert_eq!(chip.memory, memory);
+        assert_eq!(chip.frame, frame);
+        assert_eq!((chip.hires(), chip.width, chip.height), (true, 128, 64));
+        assert_eq!(chip.save_state(), state);
+
+        chip.reset();
+        assert!(matches!(chip.load_state(&state[..state.len() - 1]), Err(Chip8Error::InvalidState)));
+        let mut newer = state.clone();
+        newer[4] = STATE_VERSION + 1;
+        assert!(matches!(chip.load_state(&newer), Err(Chip8Error::InvalidState)));
+        assert!(matches!(chip.load_state(b"C8ST"), Err(Chip8Error::InvalidState)));
+        assert_eq!((chip.pc, chip.width), (ADDR_PROGRAM_START, 64));
+    }
+
+    #[test]
+    fn test_load_state_rom() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 1 / JP 0x202
+        chip.load_rom_bytes(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        let state = chip.save_state();
+
+        // Reset after loading a state from another game restarts that game
+        chip.load_rom_bytes(&[0x60, 0x02, 0x12, 0x02, 0x00, 0xE0]).unwrap();
+        chip.load_state(&state).unwrap();
+        assert_eq!(chip.rom(), [0x60, 0x01, 0x12, 0x02]);
+        chip.handle_event(&key_event(Keycode::F1, true));
+        chip.step();
+        assert_eq!(chip.v[0], 1);
+        assert_eq!(chip.memory[0x204..0x206], [0, 0]);
+    }
+
+    #[test]
+    fn test_load_state_size() {
+        let mut chip = Chip8::new_headless();
+        let low = chip.save_state();
+        chip.run_op_code(0x00FF);
+        let high = chip.save_state();
+
+        // The hires flag sits before the display size and the frame
+        let flag = |state: &[u8], width: usize, height: usize| state.len() - width * height - 5;
+        let mut low_flagged_high = low.clone();
+        low_flagged_high[flag(&low, 64, 32)] = 1;
+        let mut high_flagged_low = high.clone();
+        high_flagged_low[flag(&high, 128, 64)] = 0;
+        for state in [&low_flagged_high, &high_flagged_low] {
+            assert!(matches!(chip.load_state(state), Err(Chip8Error::InvalidState)));
+        }
+        assert_eq!((chip.hires(), chip.width), (true, 128));
+
+        chip.load_state(&low).unwrap();
+        assert_eq!((chip.hires(), chip.width, chip.height), (false, 64, 32));
+        chip.load_state(&high).unwrap();
+        assert_eq!((chip.hires(), chip.width, chip.height), (true, 128, 64));
+    }
+
+    #[test]
+    fn test_drw_16x16() {
+        let mut chip = Chip8::new_headless();
+        chip.i = 0x300;
+        for row in 0..16 { chip.memory[0x300 + row * 2..0x302 + row * 2].copy_from_slice(&[0xFF, 0x81]); }
+
+        // Low resolution Dxy0 draws nothing, as on CHIP-8
+        chip.run_op_code(0xD000);
+        assert!(chip.frame.iter().flatten().all(|&pixel| pixel == 0));
+
+        chip.run_op_code(0x00FF);
+        chip.v[0] = 120;
+        chip.v[1] = 2;
+        chip.run_op_code(0xD010);
+        assert_eq!(chip.v[0xF], 0);
+        for y in 2..18 {
+            let row: Vec<u8> = (120..136).map(|x| chip.frame[y][x % 128]).collect();
+            assert_eq!(row, [1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1], "row {}", y);
+        }
+        assert_eq!(chip.frame[1][120], 0);
+        assert_eq!(chip.frame[18][120], 0);
+
+        chip.set_quirks(Quirks::schip());
+        chip.v[0] = 120;
+        chip.run_op_code(0xD010);
+        // The pixels past the right edge are clipped this time, leaving the wrapped ones lit
+        assert_eq!(chip.v[0xF], 16);
+        assert_eq!(chip.frame[2][..8], [1, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(chip.frame[2][120..].iter().all(|&pixel| pixel == 0));
     }
 
     #[test]
-    fn test_4xkk() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-
-        // Vx != kk
-        chip.v[2] = 0x12;
-        chip.run_op_code(0x4211);
-        assert_eq!(chip.pc, 0x204);
+    fn test_scroll() {
+        let mut chip = Chip8::headless_with_display(8, 8);
+        chip.frame[0][0] = 1;
+        chip.frame[7][7] = 1;
+
+        // 00C4 moves down 2 low resolution rows
+        chip.run_op_code(0x00C4);
+        assert_frame_ascii(&chip, "........\n........\n#.......");
+        chip.run_op_code(0x00FB);
+        assert_frame_ascii(&chip, "........\n........\n..#.....");
+        chip.run_op_code(0x00FC);
+        chip.run_op_code(0x00FC);
+        assert_frame_ascii(&chip, "........\n........\n........");
+
+        chip.run_op_code(0x00FF);
+        chip.frame[0][4] = 1;
+        chip.frame[0][15] = 1;
+        chip.run_op_code(0x00C1);
+        assert_eq!(chip.frame[1][4], 1);
+        chip.run_op_code(0x00FB);
+        assert_eq!((chip.frame[1][8], chip.frame[1][15]), (1, 0));
+        assert_eq!(chip.frame[1][..4], [0; 4]);
+        chip.run_op_code(0x00FC);
+        assert_eq!(chip.frame[1][4], 1);
+        chip.run_op_code(0x00CF);
+        assert!(chip.frame.iter().flatten().all(|&pixel| pixel == 0));
+        assert_eq!(chip.pc, ADDR_PROGRAM_START + 2 * 9);
+    }
 
-        // Vx == kk
-        chip.v[2] = 0x11;
-        chip.run_op_code(0x4211);
-        assert_eq!(chip.pc, 0x206);
+    #[test]
+    fn test_frame_delta() {
+        let mut chip = Chip8::new_headless();
+        assert!(chip.take_frame_delta().is_empty());
+        chip.enable_frame_delta();
+        assert_eq!(chip.take_frame_delta(), [FrameChange::Clear { width: 64, height: 32 }]);
+
+        // LD V0, 8 / LD F, V0 / DRW V0, V0, 5 / CLS
+        chip.load_rom_bytes(&[0x60, 0x08, 0xF0, 0x29, 0xD0, 0x05, 0x00, 0xE0]).unwrap();
+        chip.take_frame_delta();
+        chip.step();
+        chip.step();
+        chip.step();
+        let drawn = chip.take_frame_delta();
+        assert_eq!(drawn.len(), 16);
+        assert_eq!(drawn[0], FrameChange::Pixel { x: 8, y: 8, value: 1 });
+
+        let mut mirror = vec![vec![0; chip.width]; chip.height];
+        delta::apply(&mut mirror, &drawn);
+        assert_eq!(mirror, chip.frame);
+
+        chip.step();
+        assert_eq!(chip.take_frame_delta(), [FrameChange::Clear { width: 64, height: 32 }]);
+        assert!(chip.take_frame_delta().is_empty());
     }
 
     #[test]
-    fn test_5xy0() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_resolution_switch() {
+        let mut chip = Chip8::new_headless();
+        chip.enable_frame_delta();
+        let mut mirror = Vec::new();
+        delta::apply(&mut mirror, &chip.take_frame_delta());
+        assert_eq!((mirror[0].len(), mirror.len()), (64, 32));
+
+        // The frame, the window and mirrors of the display all take the new size
+        for (op_code, width, height) in [(0x00FF, 128, 64), (0x00FE, 64, 32)] {
+            chip.frame[3][5] = 1;
+            chip.run_op_code(op_code);
+            assert_eq!((chip.frame[0].len(), chip.frame.len()), (width, height));
+            assert!(chip.frame.iter().flatten().all(|&pixel| pixel == 0));
+            assert_eq!(chip.window_size(), (width as u32 * 10, height as u32 * 10));
+            delta::apply(&mut mirror, &chip.take_frame_delta());
+            assert_eq!(mirror, chip.frame);
+        }
+    }
 
-        // Vx == Vy
-        chip.v[2] = 0x2;
-        chip.v[3] = 0x2;
-        chip.run_op_code(0x5230);
-        assert_eq!(chip.pc, 0x204);
+    #[test]
+    fn test_call_stack() {
+        let mut chip = Chip8::new_headless();
+        // CALL 0x206 / - / - / CALL 0x20C / - / - / RET
+        chip.memory[0x200..0x202].copy_from_slice(&[0x22, 0x06]);
+        chip.memory[0x206..0x208].copy_from_slice(&[0x22, 0x0C]);
+        chip.memory[0x20C..0x20E].copy_from_slice(&[0x00, 0xEE]);
 
-        // Vx != Vy
-        chip.v[3] = 0x3;
-        chip.run_op_code(0x5230);
-        assert_eq!(chip.pc, 0x206);
+        assert!(chip.call_stack().is_empty());
+        chip.step();
+        chip.step();
+        assert_eq!(chip.call_stack(), [0x200, 0x206]);
+        chip.step();
+        assert_eq!(chip.call_stack(), [0x200]);
     }
 
     #[test]
-    fn test_6xkk() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_stack_faults() {
+        let mut chip = Chip8::new_headless();
 
-        chip.run_op_code(0x6233);
-        assert_eq!(chip.v[2], 0x33);
-        assert_eq!(chip.pc, 0x202);
+        chip.run_op_code(0x00EE);
+        assert_eq!(chip.fault(), Some(Fault::StackUnderflow));
+        assert_eq!(chip.pc, 0x200);
+
+        chip.fault = None;
+        for _ in 0..32 { chip.run_op_code(0x2200); }
+        assert_eq!(chip.fault(), None);
+        chip.run_op_code(0x2200);
+        assert_eq!(chip.fault(), Some(Fault::StackOverflow));
+        assert_eq!(chip.sp, 32);
+
+        chip.fault = None;
+        chip.load_rom_bytes(&[0x22, 0x00]).unwrap();
+        chip.sp = 31;
+        assert!(chip.try_step().is_ok());
+        let error = chip.try_step().unwrap_err();
+        assert!(matches!(error, Chip8Error::Fault { fault: Fault::StackOverflow, pc: 0x200 }));
+        assert_eq!(error.to_string(), "StackOverflow at 0x200");
+        assert!(chip.try_step().is_err());
+
+        chip.reset();
+        chip.load_rom_bytes(&[0x00, 0xEE]).unwrap();
+        assert!(matches!(chip.try_step(), Err(Chip8Error::Fault { fault: Fault::StackUnderflow, .. })));
+
+        chip.fault = None;
+        chip.sp = 32;
+        chip.stack[31] = 0x0FF;
+        chip.run_op_code(0x00EE);
+        assert_eq!(chip.fault(), Some(Fault::InvalidReturn(0x0FF)));
+        assert_eq!((chip.sp, chip.pc), (32, 0x200));
     }
 
     #[test]
-    fn test_7xkk() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-
-        chip.v[2] = 0x2;
-        chip.run_op_code(0x7201);
-        assert_eq!(chip.v[2], 0x3);
-        assert_eq!(chip.pc, 0x202);
+    fn test_set_scale() {
+        let mut chip = Chip8::new_headless();
+        assert_eq!(chip.window_size(), (640, 320));
+
+        chip.set_scale(20);
+        assert_eq!(chip.scale(), 20);
+        assert_eq!(chip.window_size(), (1280, 640));
+
+        chip.set_show_keys(true);
+        assert_eq!(chip.window_size(), (1280, 640 + KEYPAD_PANEL_HEIGHT));
+        chip.set_debug_panel(true);
+        assert_eq!(chip.window_size(), (1280 + DEBUG_PANEL_WIDTH, 640 + KEYPAD_PANEL_HEIGHT));
+        chip.set_debug_panel(false);
+
+        chip.set_scale(0);
+        assert_eq!(chip.scale(), MIN_SCALE);
+        chip.set_scale(1000);
+        assert_eq!(chip.scale(), MAX_SCALE);
+
+        chip.fit_window_width(800);
+        assert_eq!(chip.scale(), 12);
+        assert_eq!(scale_for_width(128, 800), 6);
+        assert_eq!(scale_for_width(64, 10), MIN_SCALE);
+    }
+
+    fn key_event(keycode: Keycode, down: bool) -> Event {
+        let (timestamp, window_id, scancode, keymod, repeat) = (0, 0, None, sdl2::keyboard::Mod::NOMOD, false);
+        let keycode = Some(keycode);
+        if down {
+            Event::KeyDown { timestamp, window_id, keycode, scancode, keymod, repeat }
+        } else {
+            Event::KeyUp { timestamp, window_id, keycode, scancode, keymod, repeat }
+        }
     }
 
     #[test]
-    fn test_8xy0() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_quit_key() {
+        let mut chip = Chip8::new_headless();
+        assert!(!chip.handle_event(&key_event(Keycode::Q, true)));
+        assert!(chip.handle_event(&key_event(Keycode::Escape, true)));
 
-        chip.v[1] = 0x2;
-        chip.v[2] = 0x3;
-        chip.run_op_code(0x8120);
-        assert_eq!(chip.v[1], 0x3);
-        assert_eq!(chip.pc, 0x202);
+        chip.set_quit_key(Keycode::Q);
+        chip.keypad.clear_keys();
+        assert!(!chip.handle_event(&key_event(Keycode::Escape, true)));
+        assert!(chip.handle_event(&key_event(Keycode::Q, true)));
+        assert_eq!(chip.pressed_keys(), 0);
     }
 
     #[test]
-    fn test_8xy1() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_key_events() {
+        let mut chip = Chip8::new_headless();
+        chip.handle_event(&key_event(Keycode::Q, true));
+        chip.handle_event(&key_event(Keycode::W, true));
+        assert_eq!(chip.pressed_keys(), 1 << 4 | 1 << 5);
 
-        chip.v[1] = 0xF0;
-        chip.v[2] = 0x0F;
-        chip.run_op_code(0x8121);
-        assert_eq!(chip.v[1], 0xFF);
-        assert_eq!(chip.pc, 0x202);
+        chip.handle_event(&key_event(Keycode::Q, false));
+        assert_eq!(chip.pressed_keys(), 1 << 5);
+        chip.handle_event(&Event::Window { timestamp: 0, window_id: 0, win_event: WindowEvent::FocusLost });
+        assert_eq!(chip.pressed_keys(), 0);
     }
 
     #[test]
-    fn test_8xy2() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_keypad_grid() {
+        let grid = keypad_grid((1 << 0x1) | (1 << 0x0) | (1 << 0xF));
 
-        chip.v[1] = 0xFF;
-        chip.v[2] = 0x0F;
-        chip.run_op_code(0x8122);
-        assert_eq!(chip.v[1], 0x0F);
-        assert_eq!(chip.pc, 0x202);
+        assert_eq!(grid[0][0], (0x1, true));
+        assert_eq!(grid[0][3], (0xC, false));
+        assert_eq!(grid[3][1], (0x0, true));
+        assert_eq!(grid[3][3], (0xF, true));
+        assert_eq!(grid.iter().flatten().filter(|(_, pressed)| *pressed).count(), 3);
     }
 
     #[test]
-    fn test_8xy3() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_register_panel() {
+        let mut v = [0; 16];
+        v[0x3] = 0x2A;
+        v[0xF] = 0x01;
+        let state = MachineState { v, i: 0x2F0, pc: 0x20C, sp: 2, dt: 0x3C, st: 0 };
 
-        chip.v[1] = 0xF0;
-        chip.v[2] = 0xFF;
-        chip.run_op_code(0x8123);
-        assert_eq!(chip.v[1], 0x0F);
-        assert_eq!(chip.pc, 0x202);
+        let lines = register_panel(&state);
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], "V0:00 V8:00");
+        assert_eq!(lines[3], "V3:2A VB:00");
+        assert_eq!(lines[7], "V7:00 VF:01");
+        assert_eq!(lines[8..], ["I:02F0", "PC:020C", "SP:02", "DT:3C", "ST:00"]);
     }
 
     #[test]
-    fn test_8xy4() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_ruler_ticks() {
+        let (columns, rows) = ruler_ticks(64, 32, 10);
+        assert_eq!(columns, [80, 160, 240, 320, 400, 480, 560]);
+        assert_eq!(rows, [80, 160, 240]);
 
-        chip.v[1] = 0xAA;
-        chip.v[2] = 0xAA;
-        chip.run_op_code(0x8124);
-        assert_eq!(chip.v[1], 0x54);
-        assert_eq!(chip.v[0xF], 1);
-        assert_eq!(chip.pc, 0x202);
+        let (columns, rows) = ruler_ticks(128, 64, 5);
+        assert_eq!(columns.len(), 15);
+        assert_eq!(rows.last(), Some(&280));
+    }
 
-        chip.v[1] = 0x11;
-        chip.v[2] = 0x22;
-        chip.run_op_code(0x8124);
-        assert_eq!(chip.v[1], 0x33);
-        assert_eq!(chip.v[0xF], 0);
-        assert_eq!(chip.pc, 0x204);
+    #[test]
+    fn test_screenshot() {
+        let mut chip = Chip8::new_headless();
+        chip.set_palette(Palette::grayscale());
+        chip.frame[0][0] = 0b11;
+        chip.frame[1][2] = 0b01;
+        let path = std::env::temp_dir().join("chip8_screenshot.png");
+        let path = path.to_str().unwrap();
+
+        chip.set_scale(2);
+        chip.screenshot(path).unwrap();
+        assert_eq!(fs::read(path).unwrap()[16..24], [0, 0, 0, 128, 0, 0, 0, 64]);
+
+        // At scale 1 the scanlines fit one stored deflate block, starting after the headers
+        chip.set_scale(1);
+        chip.screenshot(path).unwrap();
+        let png = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(png[16..24], [0, 0, 0, 64, 0, 0, 0, 32]);
+        let pixel = |x: usize, y: usize| {
+            let start = 48 + y * (64 * 3 + 1) + 1 + x * 3;
+            png[start..start + 3].to_vec()
+        };
+        assert_eq!(pixel(0, 0), [255, 255, 255]);
+        assert_eq!(pixel(1, 0), [0, 0, 0]);
+        assert_eq!(pixel(2, 1), [85, 85, 85]);
+
+        assert!(matches!(chip.screenshot("no/such/dir/shot.png"), Err(Chip8Error::Io(_))));
+        assert_eq!(timestamped_path("screenshot", "png", UNIX_EPOCH + Duration::from_millis(1234)), "screenshot-1234.png");
     }
 
     #[test]
-    fn test_8xy5() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_fade() {
+        let mut chip = Chip8::new_headless();
+        chip.set_fade(true);
+        chip.v[0] = 8;
+        chip.run_op_code(0xF029);
+        chip.run_op_code(0xD005);
+        let fade = |chip: &Chip8| chip.fade.as_ref().unwrap()[8][8];
+        assert_eq!(fade(&chip), 255);
+        assert_eq!(chip.pixel_color(8, 8), (255, 255, 255));
 
-        chip.v[1] = 0xFF;
-        chip.v[2] = 0x11;
-        chip.run_op_code(0x8125);
-        assert_eq!(chip.v[1], 0xEE);
-        assert_eq!(chip.v[0xF], 1);
-        assert_eq!(chip.pc, 0x202);
+        chip.run_op_code(0x00E0);
+        assert_eq!(fade(&chip), 255);
+        let mut intensities = Vec::new();
+        for _ in 0..5 {
+            chip.end_frame();
+            intensities.push(fade(&chip));
+        }
+        assert_eq!(intensities, [191, 127, 63, 0, 0]);
 
-        chip.v[1] = 0x11;
-        chip.v[2] = 0xFF;
-        chip.run_op_code(0x8125);
-        assert_eq!(chip.v[1], 0x12);
-        assert_eq!(chip.v[0xF], 0);
-        assert_eq!(chip.pc, 0x204);
+        chip.end_frame();
+        chip.run_op_code(0xD005);
+        chip.run_op_code(0xD005);
+        chip.end_frame();
+        assert_eq!(fade(&chip), 191);
+        assert_eq!(chip.pixel_color(8, 8), (191, 191, 191));
+        assert_eq!(chip.pixel_color(12, 8), (0, 0, 0));
+
+        chip.run_op_code(0x00FF);
+        chip.end_frame();
+        assert_eq!(chip.fade.as_ref().unwrap().len(), 64);
+        chip.set_fade(false);
+        assert_eq!(chip.pixel_color(8, 8), (0, 0, 0));
     }
 
     #[test]
-    fn test_8xy6() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_recording() {
+        let mut chip = Chip8::new_headless();
+        let path = std::env::temp_dir().join("chip8_recording.gif");
+        let path = path.to_str().unwrap();
+        // LD V0, 8 / LD F, V0 / DRW V0, V0, 5 / JP 0x206
+        chip.load_rom_bytes(&[0x60, 0x08, 0xF0, 0x29, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+        assert!(chip.stop_recording().is_ok());
+
+        chip.set_scale(1);
+        chip.start_recording(path);
+        chip.end_frame();
+        chip.run_unthrottled(3);
+        // Paused frames aren't recorded
+        chip.set_paused(true);
+        chip.step_frame();
+        assert!(chip.is_recording());
+        chip.stop_recording().unwrap();
+        assert!(!chip.is_recording());
+
+        let gif = fs::read(path).unwrap();
+        fs::remove_file(path).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(gif[6..10], [64, 0, 32, 0]);
+        assert_eq!(*gif.last().unwrap(), 0x3B);
+        // A blank frame of one tick then the drawn digit for three
+        let delays: Vec<_> = gif.windows(8)
+            .filter(|window| window[..4] == [0x21, 0xF9, 0x04, 0x00] && window[6..] == [0, 0])
+            .map(|window| window[4])
+            .collect();
+        assert_eq!(delays, [1, 5]);
+    }
 
-        chip.v[5] = 14;
-        chip.run_op_code(0x8506);
-        assert_eq!(chip.v[0xF], 0);
-        assert_eq!(chip.v[5], 7);
+    #[test]
+    fn test_screenshot_due() {
+        let interval = Duration::from_millis(100);
+        assert!(!screenshot_due(Duration::from_millis(0), interval));
+        assert!(!screenshot_due(Duration::from_millis(99), interval));
+        assert!(screenshot_due(Duration::from_millis(100), interval));
+        assert!(screenshot_due(Duration::from_millis(250), interval));
     }
 
     #[test]
-    fn test_8xy7() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_seeded_rnd() {
+        let run = |seed: u64| {
+            let mut chip = Chip8::new_headless();
+            chip.seed_rng(seed);
+            assert_eq!(chip.current_seed(), seed);
+            (0..16).map(|_| { chip.rnd_vx_byte(0, 0xFF); chip.v[0] }).collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_trace_write_error() {
+        let mut chip = Chip8::new_headless();
+        chip.set_safe_mode(true);
+        // JP 0x200
+        chip.load_rom_bytes(&[0x12, 0x00]).unwrap();
+        chip.start_trace("/dev/full").unwrap();
 
-        chip.v[1] = 0x1;
-        chip.v[2] = 0x2;
-        chip.run_op_code(0x8127);
-        assert_eq!(chip.v[0xF], 1);
-        assert_eq!(chip.v[1], 0x1);
-        assert_eq!(chip.pc, 0x202);
+        // Enough records to fill the write buffer
+        for _ in 0..10_000 { chip.step(); }
+        assert_eq!(chip.fault(), None);
+        assert!(chip.trace.is_none());
+        assert!(chip.stop_trace().is_err());
+        assert!(chip.stop_trace().is_ok());
+    }
 
-        chip.v[1] = 0x2;
-        chip.v[2] = 0x1;
-        chip.run_op_code(0x8127);
-        assert_eq!(chip.v[0xF], 0);
-        assert_eq!(chip.v[1], 0xFF);
-        assert_eq!(chip.pc, 0x204);
+    #[test]
+    fn test_load_rom_errors() {
+        let mut chip = Chip8::new_headless();
+        assert!(matches!(chip.load_rom("no/such/rom.ch8"), Err(Chip8Error::Io(_))));
+
+        let path = std::env::temp_dir().join("chip8_too_large.ch8");
+        fs::write(&path, [0; MAX_ROM_SIZE + 1]).unwrap();
+        let error = chip.load_rom(path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(error, Chip8Error::RomTooLarge { size: 3585 }));
+        assert_eq!(error.to_string(), "rom is 3585 bytes, at most 3584 fit in memory");
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn test_8xye() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_reload_changed_rom() {
+        let path = std::env::temp_dir().join("chip8_watched.ch8");
+        let touch = |contents: &[u8], secs: u64| {
+            fs::write(&path, contents).unwrap();
+            let file = fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+        };
+        touch(&[0x60, 0x01], 100);
+        let mut chip = Chip8::new_headless();
+        chip.load_rom(path.to_str().unwrap()).unwrap();
+        chip.watch_rom(path.to_str().unwrap());
+        chip.step();
+
+        // Too large, the running rom keeps going
+        touch(&[0; MAX_ROM_SIZE + 1], 200);
+        chip.reload_changed_rom();
+        assert_eq!((chip.pc, chip.v[0]), (0x202, 1));
+        assert_eq!(chip.memory[0x200..0x202], [0x60, 0x01]);
+
+        touch(&[0x60, 0x02], 300);
+        chip.reload_changed_rom();
+        assert_eq!((chip.pc, chip.v[0]), (0x200, 0));
+        assert_eq!(chip.memory[0x200..0x202], [0x60, 0x02]);
+        fs::remove_file(&path).unwrap();
+    }
 
-        chip.v[1] = 0xAA;
-        chip.run_op_code(0x810E);
-        assert_eq!(chip.v[0xF], 1);
-        assert_eq!(chip.v[1], 0x54);
-        assert_eq!(chip.pc, 0x202);
+    #[test]
+    fn test_load_rom_bytes() {
+        let mut chip = Chip8::new_headless();
+        assert!(chip.load_rom_bytes(&[0xA2, 0x04, 0xD0, 0x11, 0x80]).is_ok());
+        assert_eq!(chip.memory[0x200..0x206], [0xA2, 0x04, 0xD0, 0x11, 0x80, 0x00]);
 
-        chip.run_op_code(0x810E);
-        assert_eq!(chip.v[0xF], 0);
-        assert_eq!(chip.v[1], 0xA8);
-        assert_eq!(chip.pc, 0x204);
+        assert!(chip.load_rom_bytes(&[0xFF; MAX_ROM_SIZE]).is_ok());
+        assert_eq!(chip.memory[0xFFF], 0xFF);
+        assert!(matches!(chip.load_rom_bytes(&[0; MAX_ROM_SIZE + 1]), Err(Chip8Error::RomTooLarge { size: 3585 })));
     }
 
     #[test]
-    fn test_9xy0() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_power_on_fill() {
+        let mut chip = Chip8::new_headless();
+        chip.load_rom_bytes(&[0x60, 0x01]).unwrap();
+        chip.set_power_on_fill(0xAA);
+        chip.reset();
 
-        chip.v[1] = 1;
-        chip.v[2] = 2;
-        chip.run_op_code(0x9120);
-        assert_eq!(chip.pc, 0x204);
+        assert_eq!(chip.v, [0xAA; 16]);
+        assert_eq!(chip.memory[..80], FONT_SET);
+        assert!(chip.memory[80..0x200].iter().all(|&b| b == 0xAA));
+        assert_eq!(chip.memory[0x200..0x202], [0x60, 0x01]);
+        assert!(chip.memory[0x202..].iter().all(|&b| b == 0xAA));
+    }
 
-        chip.v[1] = 2;
-        chip.run_op_code(0x9120);
-        assert_eq!(chip.pc, 0x206);
+    #[test]
+    fn test_reset() {
+        let mut chip = Chip8::new_headless();
+        // LD V0, 8 / LD F, V0 / CALL 0x208 / - / DRW V0, V0, 5 / LD DT, V0
+        let rom = [0x60, 0x08, 0xF0, 0x29, 0x22, 0x08, 0x00, 0x00, 0xD0, 0x05, 0xF0, 0x15];
+        chip.load_rom_bytes(&rom).unwrap();
+        for _ in 0..5 { chip.step(); }
+        chip.st = 3;
+        assert_ne!(chip.state(), Chip8::new_headless().state());
+        // As a rom overwriting its own code would
+        chip.memory[0x200..0x204].copy_from_slice(&[0x12, 0x00, 0x12, 0x00]);
+
+        chip.handle_event(&key_event(Keycode::F1, true));
+        assert_eq!(chip.state(), MachineState { v: [0; 16], i: 0x200, pc: 0x200, sp: 0, dt: 0, st: 0 });
+        assert_eq!(chip.stack, [0; 32]);
+        assert!(chip.frame.iter().flatten().all(|&pixel| pixel == 0));
+        assert_eq!(chip.memory[..80], FONT_SET);
+        assert_eq!(chip.rom(), rom);
+        assert_eq!(chip.memory[0x200..0x200 + rom.len()], rom);
+
+        for _ in 0..5 { chip.step(); }
+        assert_eq!((chip.v[0], chip.dt), (8, 8));
     }
 
     #[test]
-    fn test_annn() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_odd_pc() {
+        let mut chip = Chip8::new_headless();
+        // JP 0x203 / LD V1, 0x60 / LD V2, 0x00
+        chip.load_rom_bytes(&[0x12, 0x03, 0x61, 0x60, 0x62, 0x00]).unwrap();
+
+        chip.step();
+        chip.step();
+        assert_eq!(chip.fault(), None);
+        assert_eq!(chip.v[0], 0x62);
+        assert_eq!(chip.pc, 0x205);
+
+        chip.reset();
+        chip.set_strict_alignment(true);
+        chip.step();
+        chip.step();
+        assert_eq!(chip.fault(), Some(Fault::MisalignedPc(0x203)));
+        assert_eq!(chip.v[0], 0);
+        assert_eq!(chip.pc, 0x203);
+    }
 
-        chip.run_op_code(0xA123);
-        assert_eq!(chip.i, 0x123);
-        assert_eq!(chip.pc, 0x202);
+    #[test]
+    fn test_break_on_collision() {
+        let mut chip = Chip8::new_headless();
+        chip.set_break_on_collision(true);
+        // LD F, V0 / DRW V0, V0, 5 / DRW V1, V0, 5 / DRW V0, V0, 5 / DRW V0, V0, 5 / DRW V0, V0, 5
+        chip.memory[0x200..0x20C]
+            .copy_from_slice(&[0xF0, 0x29, 0xD0, 0x05, 0xD1, 0x05, 0xD0, 0x05, 0xD0, 0x05, 0xD0, 0x05]);
+        chip.v[1] = 20;
+
+        for _ in 0..3 { chip.step(); }
+        assert_eq!(chip.fault(), None);
+        chip.step();
+        assert_eq!(chip.fault(), Some(Fault::Collision));
+        assert_eq!(chip.pc, 0x208);
+        chip.step();
+        assert_eq!(chip.pc, 0x208);
+
+        // One-shot, the next collision doesn't halt
+        chip.fault = None;
+        chip.step();
+        chip.step();
+        assert_eq!(chip.v[0xF], 1);
+        assert_eq!(chip.fault(), None);
     }
 
     #[test]
-    fn test_bnnn() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_register_watch() {
+        let mut chip = Chip8::new_headless();
+        chip.add_register_watch(3, 0x05);
+        // LD V3, 4 / ADD V3, 1 / LD V4, 9
+        chip.memory[0x200..0x206].copy_from_slice(&[0x63, 0x04, 0x73, 0x01, 0x64, 0x09]);
+
+        chip.step();
+        assert_eq!(chip.fault(), None);
+        chip.step();
+        assert_eq!(chip.fault(), Some(Fault::RegisterWatch { reg: 3, value: 0x05 }));
+        assert_eq!(chip.pc, 0x204);
+        chip.step();
+        assert_eq!(chip.v[4], 0);
 
-        chip.v[0] = 2;
-        chip.run_op_code(0xB123);
-        assert_eq!(chip.pc, 0x125);
+        chip.fault = None;
+        chip.step();
+        assert_eq!(chip.v[4], 9);
+        assert_eq!(chip.fault(), None);
     }
 
     #[test]
-    fn test_cxkk() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_safe_mode() {
+        let mut chip = Chip8::new_headless();
+        chip.set_safe_mode(true);
 
-        chip.v[1] = 1;
-        chip.run_op_code(0xC1AA);
-        assert_ne!(chip.v[1], 1);
+        // Stands in for an opcode handler missing its bounds check
+        chip.run_guarded(|chip| chip.stack[chip.sp as usize + chip.stack.len()] = chip.pc);
+        assert_eq!(chip.fault(), Some(Fault::InternalPanic));
+
+        chip.memory[0x200..0x202].copy_from_slice(&[0x60, 0x01]);
+        chip.step();
+        assert_eq!(chip.pc, 0x200);
+        assert_eq!(chip.v[0], 0);
+
+        chip.fault = None;
+        chip.step();
         assert_eq!(chip.pc, 0x202);
+        assert_eq!(chip.v[0], 1);
     }
 
     #[test]
-    fn test_dxyn() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_paused_timers() {
+        let mut chip = Chip8::new_headless();
 
-        chip.i = 0x400;
-        chip.v[0] = 2;
-        chip.v[1] = 1;
-        chip.memory[0x400] = 0b11101010;
-        chip.memory[0x401] = 0b10101100;
-        chip.memory[0x402] = 0b10101010;
-        chip.memory[0x403] = 0b11101001;
-        chip.run_op_code(0xD014);
-        assert_eq!(chip.frame[1][2..10], [1, 1, 1, 0, 1, 0, 1, 0]);
-        assert_eq!(chip.frame[2][2..10], [1, 0, 1, 0, 1, 1, 0, 0]);
-        assert_eq!(chip.frame[3][2..10], [1, 0, 1, 0, 1, 0, 1, 0]);
-        assert_eq!(chip.frame[4][2..10], [1, 1, 1, 0, 1, 0, 0, 1]);
-        assert_eq!(chip.v[0xF], 0);
-        assert_eq!(chip.pc, 0x202);
+        chip.st = 5;
+        chip.dt = 5;
+        chip.set_paused(true);
+        for _ in 0..3 { chip.step_frame(); }
+        chip.tick_timers();
+        assert_eq!((chip.st, chip.dt), (5, 5));
+        assert_eq!(chip.pc, 0x200);
 
-        chip.run_op_code(0xD004);
-        assert_eq!(chip.v[0xF], 1);
-        assert_eq!(chip.pc, 0x204);
+        chip.set_paused(false);
+        chip.step_frame();
+        assert_eq!((chip.st, chip.dt), (4, 4));
     }
 
     #[test]
-    fn test_ex9e() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_space_pauses() {
+        let mut chip = Chip8::new_headless();
+        chip.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
 
-        chip.v[1] = 1;
-        chip.keypad.down_key(Keycode::Num1);
-        chip.run_op_code(0xE19E);
-        assert_eq!(chip.pc, 0x204);
+        assert!(!chip.handle_event(&key_event(Keycode::Space, true)));
+        assert!(chip.is_paused());
+        chip.step_frame();
+        assert_eq!((chip.v[0], chip.pc), (0, 0x200));
+        assert!(chip.handle_event(&key_event(Keycode::Escape, true)));
 
-        chip.keypad.clear_keys();
-        chip.run_op_code(0xE19E);
-        assert_eq!(chip.pc, 0x206);
+        chip.handle_event(&key_event(Keycode::Space, true));
+        assert!(!chip.is_paused());
+        chip.step_frame();
+        assert_eq!(chip.v[0], CYCLES_PER_FRAME as u8 / 2);
     }
 
     #[test]
-    fn test_exa1() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_invalid_log() {
+        let mut chip = Chip8::new_headless();
 
-        chip.v[1] = 1;
-        chip.run_op_code(0xE1A1);
-        assert_eq!(chip.pc, 0x204);
+        // DB 0xFFFF / LD V0, 1 / DB 0x5121 / JP 0x200
+        chip.memory[0x200..0x208].copy_from_slice(&[0xFF, 0xFF, 0x60, 0x01, 0x51, 0x21, 0x12, 0x00]);
+        chip.step();
+        assert!(chip.invalid_op_codes().is_empty());
 
-        chip.keypad.down_key(Keycode::Num1);
-        chip.run_op_code(0xE1A1);
-        assert_eq!(chip.pc, 0x206);
+        chip.pc = 0x200;
+        chip.enable_invalid_log();
+        for _ in 0..8 { chip.step(); }
+        assert_eq!(chip.invalid_op_codes(), [(0x200, 0xFFFF), (0x204, 0x5121)]);
     }
 
     #[test]
-    fn test_fx07() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_profiling() {
+        let mut chip = Chip8::new_headless();
 
-        chip.dt = 2;
-        chip.run_op_code(0xF107);
-        assert_eq!(chip.v[1], 2);
-        assert_eq!(chip.pc, 0x202);
+        // LD V0, 0 / ADD V0, 1 / SE V0, 3 / JP 0x202 / LD [I], V0
+        chip.memory[0x200..0x20A].copy_from_slice(&[0x60, 0x00, 0x70, 0x01, 0x30, 0x03, 0x12, 0x02, 0xF0, 0x55]);
+        chip.step();
+        assert!(chip.profile.is_none());
+
+        chip.pc = 0x200;
+        chip.enable_profiling();
+        for _ in 0..10 { chip.step(); }
+        let profile = chip.profile.as_ref().unwrap();
+        let executes: Vec<u32> = (0x200..0x20A).step_by(2).map(|addr| profile.executes[addr]).collect();
+        assert_eq!(executes, [1, 3, 3, 2, 1]);
+        assert_eq!(profile.writes[chip.i as usize], 1);
     }
 
     #[test]
-    fn test_fx0a() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_load_rom_bytes_byte_swap() {
+        // CLS / LD I, 0x22A / LD V0, 0x0C / LD V1, 0x08 / DRW V0, V1, 15 / JP 0x208
+        let rom = [0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x61, 0x08, 0xD0, 0x1F, 0x12, 0x08];
+        let swapped: Vec<u8> = rom.chunks(2).flat_map(|w| [w[1], w[0]]).collect();
 
-        chip.keypad.down_key(Keycode::Num1);
-        chip.run_op_code(0xF10A);
-        assert_eq!(chip.v[1], 1);
-        assert_eq!(chip.pc, 0x202);
+        let mut chip = Chip8::new_headless();
+        chip.set_byte_swap(ByteSwap::Always);
+        chip.load_rom_bytes(&swapped).unwrap();
+        assert_eq!(chip.memory[0x200..0x20C], rom);
+        assert_eq!(chip.fetch_next_op_code(), 0x00E0);
+
+        chip.set_byte_swap(ByteSwap::Auto);
+        chip.load_rom_bytes(&swapped).unwrap();
+        assert_eq!(chip.memory[0x200..0x20C], rom);
+
+        chip.load_rom_bytes(&rom).unwrap();
+        assert_eq!(chip.memory[0x200..0x20C], rom);
     }
 
     #[test]
-    fn test_fx15() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_wait_for_key() {
+        let mut chip = Chip8::new_headless();
+        chip.set_wait_for_key(true);
+        assert!(!chip.handle_event(&Event::Window {
+            timestamp: 0,
+            window_id: 0,
+            win_event: WindowEvent::Exposed,
+        }));
+        assert!(chip.waiting_for_key);
 
-        chip.v[1] = 3;
-        chip.run_op_code(0xF115);
-        assert_eq!(chip.dt, 3);
-        assert_eq!(chip.pc, 0x202);
+        assert!(!chip.handle_event(&key_event(Keycode::Q, true)));
+        assert!(!chip.waiting_for_key);
     }
 
     #[test]
-    fn test_fx18() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_current_opcode() {
+        let mut chip = Chip8::new_headless();
+        chip.enable_profiling();
+        chip.memory[0x200..0x204].copy_from_slice(&[0x83, 0xA4, 0xFF, 0xFF]);
 
-        chip.v[1] = 3;
-        chip.run_op_code(0xF118);
-        assert_eq!(chip.st, 3);
-        assert_eq!(chip.pc, 0x202);
+        assert_eq!(chip.current_opcode(), Some(Opcode::AddVxVy { x: 3, y: 0xA }));
+        assert_eq!(chip.profile.as_ref().unwrap().executes[0x200], 0);
+        chip.step();
+        assert_eq!(chip.current_opcode(), None);
     }
 
     #[test]
-    fn test_fx1e() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_load_rom_twice() {
+        let mut chip = Chip8::new_headless();
+        chip.load_rom_bytes(&[0x11; 0x40]).unwrap();
+        chip.step();
+        chip.i = 0x300;
 
-        chip.v[1] = 2;
-        chip.run_op_code(0xF11E);
-        assert_eq!(chip.i, 0x202);
-        assert_eq!(chip.pc, 0x202);
+        chip.load_rom_bytes(&[0x60, 0x01, 0x12, 0x02]).unwrap();
+        assert_eq!(chip.memory[0x200..0x204], [0x60, 0x01, 0x12, 0x02]);
+        assert!(chip.memory[0x204..].iter().all(|&b| b == 0));
+        assert_eq!(chip.rom(), [0x60, 0x01, 0x12, 0x02]);
+        assert_eq!((chip.pc, chip.i), (0x200, 0x200));
     }
 
     #[test]
-    fn test_fx29() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_instructions() {
+        let mut chip = Chip8::new_headless();
+        chip.load_rom_bytes(&[0x62, 0x33, 0xFF, 0xFF, 0xA2, 0xF0, 0xD0, 0x15, 0x12, 0x06]).unwrap();
 
-        chip.v[1] = 1;
-        chip.run_op_code(0xF129);
-        assert_eq!(chip.i, 5);
-        assert_eq!(chip.pc, 0x202);
+        let instructions: Vec<(u16, Opcode)> = chip.instructions().collect();
+        assert_eq!(instructions, [
+            (0x200, Opcode::LdVxByte { x: 2, kk: 0x33 }),
+            (0x204, Opcode::LdIAddr { nnn: 0x2F0 }),
+            (0x206, Opcode::DrwVxVyNibble { x: 0, y: 1, n: 5 }),
+            (0x208, Opcode::JpAddr { nnn: 0x206 }),
+        ]);
+        assert_eq!(chip.instructions().filter(|(_, op)| matches!(op, Opcode::JpAddr { .. })).count(), 1);
     }
 
     #[test]
-    fn test_fx33() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_validate_rom() {
+        let mut chip = Chip8::new_headless();
+        chip.load_rom_bytes(&[0x00, 0xE0, 0x62, 0x33, 0xA2, 0xF0, 0xD0, 0x15, 0x12, 0x08]).unwrap();
+        assert_eq!(chip.validate_rom(), [(0x200, true), (0x202, true), (0x204, true), (0x206, true), (0x208, true)]);
 
-        chip.v[1] = 123;
-        chip.run_op_code(0xF133);
-        assert_eq!(chip.memory[chip.i as usize], 1);
-        assert_eq!(chip.memory[chip.i as usize + 1], 2);
-        assert_eq!(chip.memory[chip.i as usize + 2], 3);
-        assert_eq!(chip.pc, 0x202);
+        chip.load_rom_bytes(&[0xFF, 0xFF, 0x62, 0x33, 0x50, 0x01, 0x12]).unwrap();
+        assert_eq!(chip.validate_rom(), [(0x200, false), (0x202, true), (0x204, false), (0x206, false)]);
     }
 
     #[test]
-    fn test_fx55() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
-        let i = chip.i as usize;
+    fn test_rom_hash() {
+        let mut chip = Chip8::new_headless();
 
-        chip.v[0] = 0;
-        chip.v[1] = 1;
-        chip.v[2] = 2;
-        chip.run_op_code(0xF255);
-        assert_eq!(chip.memory[i], 0);
-        assert_eq!(chip.memory[i + 1], 1);
-        assert_eq!(chip.memory[i + 2], 2);
-        assert_eq!(chip.pc, 0x202);
+        chip.load_rom_bytes(&[0x00, 0xE0, 0x12, 0x02]).unwrap();
+        assert_eq!(chip.rom_hash(), 0xE375_C47C_8D02_E55D);
+
+        chip.load_rom_bytes(&[0x00, 0xE0, 0x12, 0x00]).unwrap();
+        assert_ne!(chip.rom_hash(), 0xE375_C47C_8D02_E55D);
+
+        // LD V0, 0x55 / LD I, 0x206 / LD [I], V0 / a variable stored in the rom
+        chip.load_rom_bytes(&[0x60, 0x55, 0xA2, 0x06, 0xF0, 0x55, 0x00, 0x00]).unwrap();
+        let hash = chip.rom_hash();
+        for _ in 0..3 { chip.step(); }
+        assert_eq!(chip.memory[0x206], 0x55);
+        assert_eq!(chip.rom_hash(), hash);
+        assert_eq!(chip.rom()[6], 0x00);
     }
 
     #[test]
-    fn test_fx65() {
-        let mut chip = Chip8::new(&sdl2::init().unwrap());
+    fn test_last_collision_count() {
+        let mut chip = Chip8::new_headless();
 
-        chip.memory[chip.i as usize] = 0;
-        chip.memory[chip.i as usize + 1] = 1;
-        chip.memory[chip.i as usize + 2] = 2;
-        chip.run_op_code(0xF265);
-        assert_eq!(chip.v[0], 0);
-        assert_eq!(chip.v[1], 1);
-        assert_eq!(chip.v[2], 2);
-        assert_eq!(chip.pc, 0x202);
+        chip.i = 0x400;
+        chip.memory[0x400] = 0b11110000;
+        chip.memory[0x401] = 0b00111100;
+        chip.memory[0x402] = 0b10000001;
+        chip.run_op_code(0xD002);
+        assert_eq!(chip.last_collision_count(), 0);
+
+        chip.i = 0x401;
+        chip.run_op_code(0xD002);
+        assert_eq!(chip.last_collision_count(), 2);
+        assert_eq!(chip.v[0xF], 1);
     }
 }
@@ -0,0 +1,57 @@
+// Display changes since the last take, for consumers that mirror the display without copying
+// every frame
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameChange {
+    // Every pixel is off in a display of this size, sent in place of an unlit change for each
+    // pixel a CLS clears. A switch of resolution clears to the new size
+    Clear { width: usize, height: usize },
+    Pixel { x: usize, y: usize, value: u8 },
+}
+
+// Changes for a frame that was replaced wholesale, a clear to its size and then its lit pixels
+pub fn full_frame(frame: &[Vec<u8>]) -> Vec<FrameChange> {
+    let lit = frame.iter().enumerate().flat_map(|(y, row)| {
+        row.iter().enumerate().filter(|(_, &value)| value != 0).map(move |(x, &value)| FrameChange::Pixel { x, y, value })
+    });
+    let clear = FrameChange::Clear { width: frame.first().map_or(0, Vec::len), height: frame.len() };
+    std::iter::once(clear).chain(lit).collect()
+}
+
+// Brings a mirrored frame up to date, reallocating it when the resolution switches. Changes
+// outside it are ignored
+pub fn apply(frame: &mut Vec<Vec<u8>>, changes: &[FrameChange]) {
+    for change in changes {
+        match *change {
+            FrameChange::Clear { width, height } => *frame = vec![vec![0; width]; height],
+            FrameChange::Pixel { x, y, value } => {
+                if let Some(pixel) = frame.get_mut(y).and_then(|row| row.get_mut(x)) { *pixel = value; }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_frame() {
+        let frame = vec![vec![0, 1], vec![1, 0]];
+        let changes = full_frame(&frame);
+        assert_eq!(changes, [
+            FrameChange::Clear { width: 2, height: 2 },
+            FrameChange::Pixel { x: 1, y: 0, value: 1 },
+            FrameChange::Pixel { x: 0, y: 1, value: 1 },
+        ]);
+
+        let mut mirror = vec![vec![1, 1], vec![1, 1]];
+        apply(&mut mirror, &changes);
+        assert_eq!(mirror, frame);
+
+        // A mirror of another size takes the new one
+        let mut mirror = vec![vec![1; 4]; 3];
+        apply(&mut mirror, &changes);
+        assert_eq!(mirror, frame);
+    }
+}
@@ -0,0 +1,71 @@
+// Built-in self-test for --self-test, checking the interpreter draws, runs its timers and beeps
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::chip8::{Chip8, FONT_SET};
+
+// Draws the digit 8 at the top left, waits for the delay timer to run out, then beeps forever
+pub const PROGRAM: [u8; 22] = [
+    0x60, 0x08, // LD V0, 8
+    0xF0, 0x29, // LD F, V0
+    0x61, 0x00, // LD V1, 0
+    0xD1, 0x15, // DRW V1, V1, 5
+    0x62, 0x06, // LD V2, 6
+    0xF2, 0x15, // LD DT, V2
+    0xF3, 0x07, // LD V3, DT
+    0x33, 0x00, // SE V3, 0
+    0x12, 0x0C, // JP 0x20C
+    0xF2, 0x18, // LD ST, V2
+    0x12, 0x14, // JP 0x214
+];
+
+// Enough for the delay timer to run out and the sound timer to start
+pub const FRAMES: u64 = 30;
+
+const ADDR_DONE: u16 = 0x214;
+
+// Runs PROGRAM without a clock and checks what it left behind
+pub fn run(chip: &mut Chip8) -> Result<(), String> {
+    chip.load_rom_bytes(&PROGRAM).map_err(|error| error.to_string())?;
+    let beeped = Rc::new(Cell::new(false));
+    let sink = Rc::clone(&beeped);
+    chip.set_on_timer_tick(move |_, st| if st > 0 { sink.set(true) });
+    chip.run_unthrottled(FRAMES);
+
+    if let Some(fault) = chip.fault() { return Err(format!("faulted with {:?}", fault)); }
+    if chip.state().pc != ADDR_DONE { return Err(String::from("delay timer did not run out")); }
+    if !beeped.get() { return Err(String::from("sound timer did not start")); }
+
+    let glyph = &FONT_SET[8 * 5..8 * 5 + 5];
+    for (y, row) in chip.frame().iter().enumerate() {
+        for (x, &pixel) in row.iter().enumerate() {
+            let expected = glyph.get(y).filter(|_| x < 8).map_or(0, |bits| (bits >> (7 - x)) & 1);
+            if pixel != expected { return Err(format!("pixel ({}, {}) is {}, expected {}", x, y, pixel, expected)); }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tui::frame_to_ascii;
+
+    #[test]
+    fn test_run() {
+        let mut chip = Chip8::new_headless();
+        assert_eq!(run(&mut chip), Ok(()));
+        assert_eq!(frame_to_ascii(chip.frame()).lines().take(6).map(|line| &line[..5]).collect::<Vec<_>>(), [
+            "####.",
+            "#..#.",
+            "####.",
+            "#..#.",
+            "####.",
+            ".....",
+        ]);
+
+        let mut chip = Chip8::new_headless();
+        chip.load_font(&[0; 80]);
+        assert_eq!(run(&mut chip), Err(String::from("pixel (0, 0) is 0, expected 1")));
+    }
+}
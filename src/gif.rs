@@ -0,0 +1,253 @@
+// Minimal animated GIF encoder for display recordings, frames are LZW compressed against a
+// four color global table
+use std::collections::HashMap;
+
+use crate::palette::Palette;
+use crate::timing::TIMER_HZ;
+
+// The pixel values of the frame, 0 to 3, are the color table indices
+const MIN_CODE_SIZE: u8 = 2;
+const MAX_CODE_SIZE: u8 = 12;
+
+// Records display frames, one per display tick. A frame identical to the one before only
+// lengthens that one's delay
+pub struct Recorder {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    scale: usize,
+    last: Option<Vec<Vec<u8>>>,
+    // Display ticks since the recording started, and the tick the last frame started on
+    ticks: u64,
+    last_start: u64,
+}
+
+impl Recorder {
+    // The image is the display at the given size and scale, frames of another size, such as after
+    // a switch to high resolution, are stretched to fit
+    pub fn new(width: usize, height: usize, scale: usize, palette: &Palette) -> Self {
+        let (image_width, image_height) = ((width * scale) as u16, (height * scale) as u16);
+        let mut data = b"GIF89a".to_vec();
+        data.extend_from_slice(&image_width.to_le_bytes());
+        data.extend_from_slice(&image_height.to_le_bytes());
+        // Global color table of 4 colors, 2 bits per primary color
+        data.extend_from_slice(&[0x91, 0, 0]);
+        for (r, g, b) in palette.colors { data.extend_from_slice(&[r, g, b]); }
+        // Loop forever
+        data.extend_from_slice(&[0x21, 0xFF, 0x0B]);
+        data.extend_from_slice(b"NETSCAPE2.0");
+        data.extend_from_slice(&[0x03, 0x01, 0x00, 0x00, 0x00]);
+        Self { data, width, height, scale, last: None, ticks: 0, last_start: 0 }
+    }
+
+    pub fn capture(&mut self, frame: &[Vec<u8>]) {
+        if self.last.as_deref() != Some(frame) {
+            self.write_last();
+            self.last = Some(frame.to_vec());
+            self.last_start = self.ticks;
+        }
+        self.ticks += 1;
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        self.write_last();
+        self.data.push(0x3B);
+        self.data
+    }
+
+    fn write_last(&mut self) {
+        let Some(frame) = self.last.take() else { return };
+        // Rounded per tick, so the delays add up to the recorded time
+        let centis = |ticks: u64| ticks * 100 / TIMER_HZ as u64;
+        let delay = (centis(self.ticks) - centis(self.last_start)).clamp(1, u16::MAX as u64) as u16;
+        self.data.extend_from_slice(&[0x21, 0xF9, 0x04, 0x00]);
+        self.data.extend_from_slice(&delay.to_le_bytes());
+        self.data.extend_from_slice(&[0x00, 0x00]);
+
+        let (image_width, image_height) = (self.width * self.scale, self.height * self.scale);
+        self.data.extend_from_slice(&[0x2C, 0, 0, 0, 0]);
+        self.data.extend_from_slice(&(image_width as u16).to_le_bytes());
+        self.data.extend_from_slice(&(image_height as u16).to_le_bytes());
+        self.data.push(0);
+
+        let (frame_width, frame_height) = (frame[0].len(), frame.len());
+        let mut indices = Vec::with_capacity(image_width * image_height);
+        for y in 0..image_height {
+            let row = &frame[y * frame_height / image_height];
+            indices.extend((0..image_width).map(|x| row[x * frame_width / image_width] & 0b11));
+        }
+        self.data.push(MIN_CODE_SIZE);
+        for block in lzw(&indices).chunks(255) {
+            self.data.push(block.len() as u8);
+            self.data.extend_from_slice(block);
+        }
+        self.data.push(0);
+    }
+}
+
+// Variable width LZW as GIF uses it, the code width grows as the table fills and the table
+// starts over once 12 bit codes run out
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let clear = 1u16 << MIN_CODE_SIZE;
+    let end = clear + 1;
+    let mut out = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+    out.write(clear, size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        out.write(end, size);
+        return out.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        out.write(prefix, size);
+        // Wider codes start once the table holds a code the current width can't
+        if next >= 1 << size && size < MAX_CODE_SIZE { size += 1; }
+        if next < 1 << MAX_CODE_SIZE {
+            table.insert((prefix, index), next);
+            next += 1;
+        } else {
+            out.write(clear, size);
+            table.clear();
+            next = end + 1;
+            size = MIN_CODE_SIZE + 1;
+        }
+        prefix = index as u16;
+    }
+    out.write(prefix, size);
+    if next >= 1 << size && size < MAX_CODE_SIZE { size += 1; }
+    out.write(end, size);
+    out.finish()
+}
+
+// Packs codes least significant bit first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    bits: u32,
+    len: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.bits |= (code as u32) << self.len;
+        self.len += size;
+        while self.len >= 8 {
+            self.bytes.push(self.bits as u8);
+            self.bits >>= 8;
+            self.len -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.len > 0 { self.bytes.push(self.bits as u8); }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reference decoder, growing the code width one entry behind the encoder as decoders do
+    fn unlzw(data: &[u8]) -> Vec<u8> {
+        let clear = 1u16 << MIN_CODE_SIZE;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = MIN_CODE_SIZE + 1;
+        let (mut bits, mut len, mut pos) = (0u32, 0u8, 0);
+        let mut previous: Option<Vec<u8>> = None;
+        let mut out = Vec::new();
+        loop {
+            while len < size {
+                bits |= (data[pos] as u32) << len;
+                pos += 1;
+                len += 8;
+            }
+            let code = (bits & ((1 << size) - 1)) as u16;
+            bits >>= size;
+            len -= size;
+
+            if code == clear {
+                table = (0..clear).map(|index| vec![index as u8]).collect();
+                table.extend([Vec::new(), Vec::new()]);
+                size = MIN_CODE_SIZE + 1;
+                previous = None;
+                continue;
+            }
+            if code == clear + 1 { return out; }
+            let entry = match (table.get(code as usize), &previous) {
+                (Some(entry), _) => entry.clone(),
+                (None, Some(previous)) => [previous.clone(), vec![previous[0]]].concat(),
+                (None, None) => panic!("Code {} before any entry", code),
+            };
+            if let Some(previous) = previous {
+                if table.len() < 1 << MAX_CODE_SIZE { table.push([previous, vec![entry[0]]].concat()); }
+                if table.len() == 1 << size && size < MAX_CODE_SIZE { size += 1; }
+            }
+            out.extend_from_slice(&entry);
+            previous = Some(entry);
+        }
+    }
+
+    #[test]
+    fn test_lzw() {
+        let short = [0, 1, 2, 3, 0, 1, 2, 3, 0, 0, 0, 0, 3];
+        assert_eq!(unlzw(&lzw(&short)), short);
+        assert_eq!(unlzw(&lzw(&[])), []);
+
+        // Long enough to widen the codes to 12 bits and clear the table
+        let mut seed = 1u32;
+        let long: Vec<u8> = (0..40_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 29) as u8 & 0b11
+            })
+            .collect();
+        assert_eq!(unlzw(&lzw(&long)), long);
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = Recorder::new(2, 1, 2, &Palette::default());
+        let (dark, lit) = (vec![vec![0, 0]], vec![vec![1, 0]]);
+        for frame in [&dark, &dark, &dark, &lit, &lit] { recorder.capture(frame); }
+        recorder.capture(&[vec![1, 0, 0, 1], vec![0, 0, 0, 0]]);
+        let gif = recorder.finish();
+
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(gif[6..11], [4, 0, 2, 0, 0x91]);
+        assert_eq!(gif[13..16], [0, 0, 0]);
+        assert_eq!(*gif.last().unwrap(), 0x3B);
+
+        // Three frames of 3, 2 and 1 ticks, each a 4x2 image
+        let mut pos = 13 + 12 + 19;
+        let mut frames = Vec::new();
+        while gif[pos] == 0x21 {
+            assert_eq!(gif[pos..pos + 4], [0x21, 0xF9, 0x04, 0x00]);
+            let delay = u16::from_le_bytes([gif[pos + 4], gif[pos + 5]]);
+            pos += 8;
+            assert_eq!(gif[pos..pos + 10], [0x2C, 0, 0, 0, 0, 4, 0, 2, 0, 0]);
+            assert_eq!(gif[pos + 10], MIN_CODE_SIZE);
+            pos += 11;
+            let mut data = Vec::new();
+            while gif[pos] != 0 {
+                data.extend_from_slice(&gif[pos + 1..pos + 1 + gif[pos] as usize]);
+                pos += 1 + gif[pos] as usize;
+            }
+            pos += 1;
+            frames.push((delay, unlzw(&data)));
+        }
+        assert_eq!(pos, gif.len() - 1);
+        assert_eq!(frames, [
+            (5, vec![0, 0, 0, 0, 0, 0, 0, 0]),
+            (3, vec![1, 1, 0, 0, 1, 1, 0, 0]),
+            (2, vec![1, 0, 0, 1, 0, 0, 0, 0]),
+        ]);
+    }
+}
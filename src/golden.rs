@@ -0,0 +1,69 @@
+use std::fs;
+use std::io;
+
+use crate::chip8::Chip8;
+
+// Fixed seed so Cxkk draws the same numbers on every run
+pub const GOLDEN_SEED: u64 = 0xC8;
+
+// Runs the loaded rom for total_frames with the key script, hashing the display after each frame
+pub fn record(chip: &mut Chip8, script: &[(u64, u16)], total_frames: u64) -> Vec<u64> {
+    chip.seed_rng(GOLDEN_SEED);
+    (0..total_frames)
+        .map(|_| {
+            chip.run_script(script, 1);
+            chip.frame_hash()
+        })
+        .collect()
+}
+
+// Index of the first frame that differs, a missing frame counts as a difference
+pub fn first_mismatch(expected: &[u64], actual: &[u64]) -> Option<usize> {
+    (0..expected.len().max(actual.len())).find(|&frame| expected.get(frame) != actual.get(frame))
+}
+
+// One hex hash per line
+pub fn write_golden(path: &str, hashes: &[u64]) -> io::Result<()> {
+    let text: String = hashes.iter().map(|hash| format!("{:016X}\n", hash)).collect();
+    fs::write(path, text)
+}
+
+pub fn read_golden(path: &str) -> io::Result<Vec<u64>> {
+    fs::read_to_string(path)?
+        .lines()
+        .map(|line| u64::from_str_radix(line.trim(), 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid golden hash!")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_verify() {
+        let path = std::env::temp_dir().join("chip8_golden.txt");
+        let path = path.to_str().unwrap();
+        // Draws a random sprite, waits for key 5 to be pressed and released and draws the font glyph for it
+        let rom = [0xC0, 0xFF, 0xA2, 0x00, 0xD0, 0x15, 0xF2, 0x0A, 0xF2, 0x29, 0xD0, 0x15, 0x12, 0x0C];
+        let script = [(3, 1 << 5), (4, 0)];
+        let run = || {
+            let mut chip = Chip8::new_headless();
+            chip.load_rom_bytes(&rom).unwrap();
+            record(&mut chip, &script, 8)
+        };
+
+        write_golden(path, &run()).unwrap();
+        let golden = read_golden(path).unwrap();
+        assert_eq!(golden.len(), 8);
+        assert_eq!(first_mismatch(&golden, &run()), None);
+        assert_eq!(golden[2], golden[3]);
+        assert_ne!(golden[3], golden[4]);
+
+        assert_eq!(first_mismatch(&golden, &golden[..5]), Some(5));
+        let mut changed = golden.clone();
+        changed[6] ^= 1;
+        assert_eq!(first_mismatch(&golden, &changed), Some(6));
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,65 @@
+use sdl2::{pixels::Color, rect::Rect, render::Canvas, video::Window};
+
+use crate::chip8::FONT_SET;
+
+const GLYPH_WIDTH: u32 = 4;
+const GLYPH_HEIGHT: usize = 5;
+
+// 4x5 glyphs for the letters the built-in font set lacks
+const LETTERS: [[u8; GLYPH_HEIGHT]; 20] = [
+    [0xF0, 0x80, 0xB0, 0x90, 0xF0], // G
+    [0x90, 0x90, 0xF0, 0x90, 0x90], // H
+    [0xE0, 0x40, 0x40, 0x40, 0xE0], // I
+    [0x70, 0x20, 0x20, 0xA0, 0xE0], // J
+    [0x90, 0xA0, 0xC0, 0xA0, 0x90], // K
+    [0x80, 0x80, 0x80, 0x80, 0xF0], // L
+    [0x90, 0xF0, 0xF0, 0x90, 0x90], // M
+    [0x90, 0xD0, 0xB0, 0x90, 0x90], // N
+    [0xF0, 0x90, 0x90, 0x90, 0xF0], // O
+    [0xF0, 0x90, 0xF0, 0x80, 0x80], // P
+    [0xF0, 0x90, 0x90, 0xB0, 0xF0], // Q
+    [0xE0, 0x90, 0xE0, 0xA0, 0x90], // R
+    [0xF0, 0x80, 0xF0, 0x10, 0xF0], // S
+    [0xF0, 0x40, 0x40, 0x40, 0x40], // T
+    [0x90, 0x90, 0x90, 0x90, 0xF0], // U
+    [0x90, 0x90, 0x90, 0xA0, 0x40], // V
+    [0x90, 0x90, 0xF0, 0xF0, 0x90], // W
+    [0x90, 0x90, 0x60, 0x90, 0x90], // X
+    [0x90, 0x90, 0x70, 0x10, 0xE0], // Y
+    [0xF0, 0x10, 0x60, 0x80, 0xF0], // Z
+];
+
+pub fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    let mut rows = [0; GLYPH_HEIGHT];
+    match c.to_ascii_uppercase() {
+        c @ ('0'..='9' | 'A'..='F') => {
+            let start = c.to_digit(16).unwrap() as usize * GLYPH_HEIGHT;
+            rows.copy_from_slice(&FONT_SET[start..start + GLYPH_HEIGHT]);
+        }
+        c @ 'G'..='Z' => rows = LETTERS[c as usize - 'G' as usize],
+        ':' => rows = [0x00, 0x40, 0x00, 0x40, 0x00],
+        '-' => rows = [0x00, 0x00, 0xF0, 0x00, 0x00],
+        '.' => rows = [0x00, 0x00, 0x00, 0x00, 0x40],
+        _ => {}
+    }
+    rows
+}
+
+// Draws text straight onto the canvas, each glyph pixel being `scale` screen pixels wide
+pub fn draw_text(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, scale: u32, color: Color) {
+    canvas.set_draw_color(color);
+    for (i, c) in text.chars().enumerate() {
+        let left = x + (i as u32 * (GLYPH_WIDTH + 1) * scale) as i32;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if bits >> (7 - col) & 1 == 0 { continue; }
+                canvas.fill_rect(Rect::new(
+                    left + (col * scale) as i32,
+                    y + (row as u32 * scale) as i32,
+                    scale,
+                    scale,
+                )).expect("Fill Rect Issue");
+            }
+        }
+    }
+}
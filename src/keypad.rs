@@ -1,55 +1,263 @@
 use sdl2::keyboard::Keycode;
 
+// Keys the emulator handles before the keypad sees them: pause, reset, screenshot, recording and
+// scale. The quit key is reserved as well
+const RESERVED_KEYS: [Keycode; 9] = [
+    Keycode::Space, Keycode::F1, Keycode::F2, Keycode::F3,
+    Keycode::Equals, Keycode::Plus, Keycode::KpPlus, Keycode::Minus, Keycode::KpMinus,
+];
+
+// Physical keys and the CHIP-8 keys they press
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    bindings: Vec<(Keycode, u8)>,
+}
+
+impl Default for KeyMap {
+    // Original             Current
+    // +---+---+---+---+    +---+---+---+---+
+    // | 1 | 2 | 3 | C |    | 1 | 2 | 3 | 4 |
+    // +---+---+---+---+    +---+---+---+---+
+    // | 4 | 5 | 6 | D |    | Q | W | E | R |
+    // +---+---+---+---+    +---+---+---+---+
+    // | 7 | 8 | 9 | E |    | A | S | D | F |
+    // +---+---+---+---+    +---+---+---+---+
+    // | A | 0 | B | F |    | Z | X | C | V |
+    // +---+---+---+---+    +---+---+---+---+
+    fn default() -> Self {
+        Self::new(vec![
+            (Keycode::Num1, 1), (Keycode::Num2, 2), (Keycode::Num3, 3), (Keycode::Num4, 0xC),
+            (Keycode::Q, 4), (Keycode::W, 5), (Keycode::E, 6), (Keycode::R, 0xD),
+            (Keycode::A, 7), (Keycode::S, 8), (Keycode::D, 9), (Keycode::F, 0xE),
+            (Keycode::Z, 0xA), (Keycode::X, 0), (Keycode::C, 0xB), (Keycode::V, 0xF),
+        ])
+    }
+}
+
+impl KeyMap {
+    pub fn new(bindings: Vec<(Keycode, u8)>) -> Self { Self { bindings } }
+
+    // The left half of the pad stays on the left hand, the right half moves to the right hand
+    // +---+---+     +---+---+
+    // | 1 | 2 |     | 9 | 0 |
+    // +---+---+     +---+---+
+    // | Q | W |     | O | P |
+    // +---+---+     +---+---+
+    // | A | S |     | L | ; |
+    // +---+---+     +---+---+
+    // | Z | X |     | . | / |
+    // +---+---+     +---+---+
+    pub fn two_player() -> Self {
+        Self::new(vec![
+            (Keycode::Num1, 1), (Keycode::Num2, 2), (Keycode::Q, 4), (Keycode::W, 5),
+            (Keycode::A, 7), (Keycode::S, 8), (Keycode::Z, 0xA), (Keycode::X, 0),
+            (Keycode::Num9, 3), (Keycode::Num0, 0xC), (Keycode::O, 6), (Keycode::P, 0xD),
+            (Keycode::L, 9), (Keycode::Semicolon, 0xE), (Keycode::Period, 0xB), (Keycode::Slash, 0xF),
+        ])
+    }
+
+    // KEY=HEX lines over the default map, such as "U=5" or "K = F", with # starting a comment.
+    // Lines that can't be read, and bindings of keys the emulator takes for itself, are skipped
+    // and described in the returned warnings
+    pub fn parse(text: &str, quit_key: Keycode) -> (Self, Vec<String>) {
+        let mut key_map = Self::default();
+        let mut warnings = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() { continue; }
+            match parse_binding(line) {
+                Some((key, _)) if key == quit_key || RESERVED_KEYS.contains(&key) => {
+                    let name = line.split('=').next().unwrap_or_default().trim();
+                    warnings.push(format!("line {}: {} is reserved and never reaches the keypad", number + 1, name));
+                }
+                Some((key, value)) => key_map.bind(key, value),
+                None => warnings.push(format!("line {}: expected KEY=HEX, got \"{}\"", number + 1, line)),
+            }
+        }
+        (key_map, warnings)
+    }
+
+    // Replaces what the key was bound to, other keys bound to the same value keep it
+    pub fn bind(&mut self, key: Keycode, value: u8) {
+        self.bindings.retain(|(bound, _)| *bound != key);
+        self.bindings.push((key, value & 0xF));
+    }
+
+    pub fn get(&self, key: Keycode) -> Option<u8> {
+        self.bindings.iter().find(|(bound, _)| *bound == key).map(|&(_, value)| value)
+    }
+}
+
+fn parse_binding(line: &str) -> Option<(Keycode, u8)> {
+    let (key, value) = line.split_once('=')?;
+    let key = Keycode::from_name(key.trim())?;
+    let value = u8::from_str_radix(value.trim(), 16).ok().filter(|&value| value < 16)?;
+    Some((key, value))
+}
+
+#[derive(Default)]
 pub struct Keypad {
-    key: Option<u8>,
+    // Bit n is set while CHIP-8 key n is down, any number of keys at once
+    keys: u16,
+    // The key pressed last, if still down, which get_key takes over lower held keys
+    last_pressed: Option<u8>,
+    // Keys that went down and keys that went up since the transitions were last reset
+    presses: u16,
+    releases: u16,
+
+    key_map: KeyMap,
 }
 
 impl Keypad {
-    pub fn new() -> Self { Self { key: None } }
+    pub fn new() -> Self { Self::default() }
 
-    pub fn is_pressed(&self, key: u8) -> bool { if let Some(i) = self.key { i == key } else { false } }
+    pub fn set_key_map(&mut self, key_map: KeyMap) { self.key_map = key_map; }
 
-    pub fn get_key(&self) -> Option<u8> { self.key }
+    pub fn is_pressed(&self, key: u8) -> bool { key < 16 && self.keys & (1 << key) != 0 }
+
+    pub fn get_key(&self) -> Option<u8> {
+        if self.keys == 0 { return None; }
+        self.last_pressed.filter(|&key| self.is_pressed(key)).or(Some(self.keys.trailing_zeros() as u8))
+    }
+
+    pub fn get_state(&self) -> u16 { self.keys }
+
+    pub fn set_state(&mut self, keys: u16) {
+        self.presses |= keys & !self.keys;
+        self.releases |= self.keys & !keys;
+        self.keys = keys;
+        self.last_pressed = None;
+    }
 
     pub fn down_keys(&mut self, keys: Vec<Keycode>) {
         for key in keys { self.down_key(key); }
     }
 
     pub fn down_key(&mut self, key: Keycode) {
-        self.key = self.get_key_value(key);
+        if let Some(i) = self.get_key_value(key) {
+            self.presses |= !self.keys & 1 << i;
+            self.keys |= 1 << i;
+            self.last_pressed = Some(i);
+        }
     }
-    
-    pub fn clear_keys(&mut self) { self.key = None }
 
-    // Original             Current
-    // +---+---+---+---+    +---+---+---+---+
-    // | 1 | 2 | 3 | C |    | 1 | 2 | 3 | 4 |
-    // +---+---+---+---+    +---+---+---+---+
-    // | 4 | 5 | 6 | D |    | Q | W | E | R |
-    // +---+---+---+---+    +---+---+---+---+
-    // | 7 | 8 | 9 | E |    | A | S | D | F |
-    // +---+---+---+---+    +---+---+---+---+
-    // | A | 0 | B | F |    | Z | X | C | V |
-    // +---+---+---+---+    +---+---+---+---+
-    fn get_key_value(&self, key: Keycode) -> Option<u8> {
-        match key {
-            Keycode::Num1 => Some(1),
-            Keycode::Num2 => Some(2),
-            Keycode::Num3 => Some(3),
-            Keycode::Num4 => Some(0xC),
-            Keycode::Q => Some(4),
-            Keycode::W => Some(5),
-            Keycode::E => Some(6),
-            Keycode::R => Some(0xD),
-            Keycode::A => Some(7),
-            Keycode::S => Some(8),
-            Keycode::D => Some(9),
-            Keycode::F => Some(0xE),
-            Keycode::Z => Some(0xA),
-            Keycode::X => Some(0),
-            Keycode::C => Some(0xB),
-            Keycode::V => Some(0xF),
-            _ => None,
+    pub fn release_key(&mut self, key: Keycode) {
+        if let Some(i) = self.get_key_value(key) {
+            self.releases |= self.keys & 1 << i;
+            self.keys &= !(1 << i);
         }
     }
+
+    pub fn reset_transitions(&mut self) {
+        self.presses = 0;
+        self.releases = 0;
+    }
+
+    // A key that was both pressed and released since the transitions were reset, and is up now.
+    // Taking one resets the transitions
+    pub fn take_press_and_release(&mut self) -> Option<u8> {
+        let completed = self.presses & self.releases & !self.keys;
+        if completed == 0 { return None; }
+        self.reset_transitions();
+        Some(completed.trailing_zeros() as u8)
+    }
+
+    pub fn clear_keys(&mut self) { self.set_state(0) }
+
+    fn get_key_value(&self, key: Keycode) -> Option<u8> { self.key_map.get(key) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_two_player() {
+        let mut keypad = Keypad::new();
+        keypad.set_key_map(KeyMap::two_player());
+
+        let player_one = [(Keycode::Num1, 1), (Keycode::Q, 4), (Keycode::S, 8), (Keycode::X, 0)];
+        let player_two = [(Keycode::Num0, 0xC), (Keycode::P, 0xD), (Keycode::L, 9), (Keycode::Slash, 0xF)];
+        for (key, value) in player_one.into_iter().chain(player_two) {
+            keypad.clear_keys();
+            keypad.down_key(key);
+            assert_eq!(keypad.get_key(), Some(value), "{}", key);
+        }
+
+        keypad.clear_keys();
+        keypad.down_keys(vec![Keycode::Num1, Keycode::Num0, Keycode::V]);
+        assert_eq!(keypad.get_state(), 1 << 1 | 1 << 0xC);
+    }
+
+    #[test]
+    fn test_parse_key_map() {
+        let (key_map, warnings) = KeyMap::parse("# Colemak\nF = 6\nu=D\n\nK=10\nNoSuchKey=1\nP\n", Keycode::Escape);
+        assert_eq!(warnings, [
+            "line 5: expected KEY=HEX, got \"K=10\"",
+            "line 6: expected KEY=HEX, got \"NoSuchKey=1\"",
+            "line 7: expected KEY=HEX, got \"P\"",
+        ]);
+        assert_eq!(key_map.get(Keycode::F), Some(6));
+        assert_eq!(key_map.get(Keycode::U), Some(0xD));
+        assert_eq!(key_map.get(Keycode::R), Some(0xD));
+        assert_eq!(key_map.get(Keycode::K), None);
+        assert_eq!(KeyMap::parse("", Keycode::Escape).0, KeyMap::default());
+    }
+
+    #[test]
+    fn test_parse_reserved_keys() {
+        let text = "Space = F\nF1=1\nF2=2\nF3=3\n+=4\n-=5\nQ=7\nEscape=8\nK=9\n";
+        let (key_map, warnings) = KeyMap::parse(text, Keycode::Q);
+        assert_eq!(warnings, [
+            "line 1: Space is reserved and never reaches the keypad",
+            "line 2: F1 is reserved and never reaches the keypad",
+            "line 3: F2 is reserved and never reaches the keypad",
+            "line 4: F3 is reserved and never reaches the keypad",
+            "line 5: + is reserved and never reaches the keypad",
+            "line 6: - is reserved and never reaches the keypad",
+            "line 7: Q is reserved and never reaches the keypad",
+        ]);
+        assert_eq!(key_map.get(Keycode::Space), None);
+        assert_eq!(key_map.get(Keycode::Escape), Some(8));
+        assert_eq!(key_map.get(Keycode::K), Some(9));
+    }
+
+    #[test]
+    fn test_chord() {
+        let mut keypad = Keypad::new();
+        keypad.down_key(Keycode::W);
+        keypad.down_key(Keycode::Q);
+        assert!(keypad.is_pressed(5) && keypad.is_pressed(4));
+        assert_eq!(keypad.get_key(), Some(4));
+
+        keypad.down_key(Keycode::V);
+        assert_eq!(keypad.get_key(), Some(0xF));
+        keypad.release_key(Keycode::V);
+        assert_eq!(keypad.get_key(), Some(4));
+        assert_eq!(keypad.get_state(), 1 << 4 | 1 << 5);
+
+        keypad.release_key(Keycode::W);
+        keypad.release_key(Keycode::Q);
+        assert_eq!(keypad.get_key(), None);
+    }
+
+    #[test]
+    fn test_press_and_release() {
+        let mut keypad = Keypad::new();
+        // Held from before the transitions were reset, so its release alone doesn't count
+        keypad.down_key(Keycode::Q);
+        keypad.reset_transitions();
+        keypad.release_key(Keycode::Q);
+        assert_eq!(keypad.take_press_and_release(), None);
+
+        keypad.down_key(Keycode::W);
+        assert_eq!(keypad.take_press_and_release(), None);
+        keypad.release_key(Keycode::W);
+        assert_eq!(keypad.take_press_and_release(), Some(5));
+        assert_eq!(keypad.take_press_and_release(), None);
+
+        keypad.set_state(1 << 0xA);
+        keypad.set_state(0);
+        assert_eq!(keypad.take_press_and_release(), Some(0xA));
+    }
 }
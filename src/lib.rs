@@ -0,0 +1,20 @@
+pub mod analysis;
+pub mod audio;
+pub mod chip8;
+pub mod delta;
+pub mod diagnostic;
+pub mod gif;
+pub mod golden;
+pub mod hud;
+pub mod keypad;
+pub mod metrics;
+pub mod opcode;
+pub mod palette;
+pub mod platform;
+pub mod png;
+pub mod quirks;
+pub mod timing;
+pub mod trace;
+pub mod tui;
+pub mod watcher;
+pub mod wav;
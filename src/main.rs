@@ -1,12 +1,25 @@
+use std::fs;
+use std::time::Duration;
+
 use clap::{App, Arg};
+use sdl2::keyboard::Keycode;
 
-use crate::chip8::Chip8;
+use chip8_interpreter::chip8::{self, ByteSwap, Chip8, Fault};
+use chip8_interpreter::keypad::KeyMap;
+use chip8_interpreter::palette::Palette;
+use chip8_interpreter::platform::{self, Platform};
+use chip8_interpreter::{analysis, diagnostic, golden, trace};
+#[cfg(feature = "tui")]
+use chip8_interpreter::tui;
 
-mod chip8;
-mod keypad;
+// 100 seconds of emulated time
+const BENCHMARK_FRAMES: u64 = 6000;
+// Under one instruction per frame, slow enough to watch each one in the debug panel
+const SLOW_IPS: u32 = 60;
+const SELF_TEST_BEEP: Duration = Duration::from_millis(250);
 
 fn main() {
-    let matches = App::new(chip8::WINDOW_TITLE)
+    let app = App::new(chip8::WINDOW_TITLE)
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
         .usage("chip8-interpreter [OPTIONS]")
@@ -19,13 +32,392 @@ fn main() {
                 .takes_value(true)
                 .empty_values(false)
                 .multiple(false)
-                .required(true)
-        ]).get_matches();
+                .required_unless_one(&["annotate_trace", "self_test"]),
+            Arg::with_name("platform")
+                .long("platform")
+                .value_name("PLATFORM")
+                .help("Sets the platform whose behavior to follow, auto picks quirks for known roms")
+                .takes_value(true)
+                .possible_values(&["auto", "chip-8", "schip", "xo-chip"]),
+            Arg::with_name("palette")
+                .long("palette")
+                .value_name("PALETTE")
+                .help("Sets the display colors")
+                .takes_value(true)
+                .possible_values(&["color", "grayscale"]),
+            Arg::with_name("shift_quirk")
+                .long("shift-quirk")
+                .help("Makes 8xy6 and 8xyE shift Vy into Vx like the COSMAC VIP"),
+            Arg::with_name("load_store_quirk")
+                .long("load-store-quirk")
+                .help("Makes Fx55 and Fx65 advance I past the registers like the COSMAC VIP"),
+            Arg::with_name("fx1e_quirk")
+                .long("fx1e-quirk")
+                .help("Makes Fx1E set VF when I passes 0xFFF like the Amiga interpreter"),
+            Arg::with_name("fade")
+                .long("fade")
+                .help("Fades cleared pixels out over a few frames to reduce flicker"),
+            Arg::with_name("debug_hud")
+                .long("debug-hud")
+                .help("Shows the collision count of the last sprite draw"),
+            Arg::with_name("debug_panel")
+                .long("debug-panel")
+                .help("Shows the registers live in a panel right of the display"),
+            Arg::with_name("ruler")
+                .long("ruler")
+                .help("Overlays tick marks every 8 pixels along the display edges"),
+            Arg::with_name("show_keys")
+                .long("show-keys")
+                .help("Shows the hex keypad state below the display"),
+            Arg::with_name("byte_swap")
+                .long("byte-swap")
+                .value_name("MODE")
+                .help("Swaps each 16-bit word of a byte-swapped rom dump")
+                .takes_value(true)
+                .possible_values(&["always", "auto"]),
+            Arg::with_name("speed")
+                .short("s")
+                .long("speed")
+                .visible_alias("ips")
+                .value_name("IPS")
+                .help("Sets the instructions run per second, the timers tick at --timer-hz regardless. \
+                    Below 60 the debug panel opens to follow each instruction")
+                .takes_value(true)
+                .default_value("700")
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(ips) if ips > 0 => Ok(()),
+                    _ => Err(String::from("must be a positive number of instructions")),
+                }),
+            Arg::with_name("timer_hz")
+                .long("timer-hz")
+                .value_name("HZ")
+                .help("Ticks the delay and sound timers this many times per second instead of 60")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(hz) if hz > 0 => Ok(()),
+                    _ => Err(String::from("must be a positive rate")),
+                }),
+            Arg::with_name("two_player")
+                .long("two-player")
+                .help("Splits the keypad between 12QWASZX and 90OPL;./ for two players"),
+            Arg::with_name("keymap")
+                .long("keymap")
+                .value_name("KEYMAP_PATH")
+                .help("Rebinds keys from a file of KEY=HEX lines, such as U=5, over the default layout")
+                .takes_value(true)
+                .conflicts_with("two_player"),
+            Arg::with_name("quit_key")
+                .long("quit-key")
+                .value_name("KEY")
+                .help("Sets the key that quits, by its SDL name, e.g. Backspace. It no longer presses a CHIP-8 key")
+                .takes_value(true)
+                .default_value("Escape")
+                .validator(|value| match Keycode::from_name(&value) {
+                    Some(_) => Ok(()),
+                    None => Err(String::from("must be an SDL key name")),
+                }),
+            Arg::with_name("wait_for_key")
+                .long("wait-for-key")
+                .help("Waits for a key press before starting the rom"),
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("CSV_PATH")
+                .help("Writes per-address read/write/execute counts to a csv file on exit")
+                .takes_value(true),
+            Arg::with_name("mute")
+                .long("mute")
+                .help("Disables the sound timer tone"),
+            Arg::with_name("log_invalid")
+                .long("log-invalid")
+                .help("Prints the address of every unknown opcode hit on exit"),
+            Arg::with_name("safe_mode")
+                .long("safe-mode")
+                .help("Halts with a fault instead of crashing if the interpreter panics"),
+            Arg::with_name("power_on_fill")
+                .long("power-on-fill")
+                .value_name("BYTE")
+                .help("Sets the hex byte registers and unused memory hold at power on")
+                .takes_value(true)
+                .validator(|value| match u8::from_str_radix(value.trim_start_matches("0x"), 16) {
+                    Ok(_) => Ok(()),
+                    Err(_) => Err(String::from("must be a hex byte")),
+                }),
+            Arg::with_name("legacy_timers")
+                .long("legacy-timers")
+                .help("Ticks the delay and sound timers after every instruction instead of at --timer-hz")
+                .conflicts_with("timer_hz"),
+            Arg::with_name("strict_alignment")
+                .long("strict-alignment")
+                .help("Halts with a fault when pc lands on an odd address"),
+            Arg::with_name("break_on_collision")
+                .long("break-on-collision")
+                .help("Halts on the first sprite collision and saves it to collision.png on exit"),
+            Arg::with_name("semihosting")
+                .long("semihosting")
+                .help("Prints every byte the rom stores at 0xFFF with Fx55, for test roms to report results"),
+            Arg::with_name("scale")
+                .long("scale")
+                .value_name("SCALE")
+                .help("Sets the size in window pixels of each display pixel")
+                .takes_value(true)
+                .default_value("10")
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(scale) if (chip8::MIN_SCALE..=chip8::MAX_SCALE).contains(&scale) => Ok(()),
+                    _ => Err(format!("must be between {} and {}", chip8::MIN_SCALE, chip8::MAX_SCALE)),
+                }),
+            Arg::with_name("window_width")
+                .long("window-width")
+                .value_name("PX")
+                .help("Scales the display to the largest whole multiple that fits PX pixels wide, overriding --scale")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(px) if px > 0 => Ok(()),
+                    _ => Err(String::from("must be a positive number of pixels")),
+                }),
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seeds the random number generator so Cxkk draws the same numbers on every run")
+                .takes_value(true)
+                .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|_| String::from("must be a number"))),
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("TRACE_PATH")
+                .help("Records every executed instruction to a binary trace file")
+                .takes_value(true),
+            Arg::with_name("annotate_trace")
+                .long("annotate-trace")
+                .value_name("TRACE_PATH")
+                .help("Prints a binary trace file as disassembly and exits")
+                .takes_value(true),
+            Arg::with_name("self_test")
+                .long("self-test")
+                .help("Runs a built-in program checking drawing, timers and sound, renders it and beeps, then exits"),
+            Arg::with_name("disassemble")
+                .long("disassemble")
+                .help("Prints the rom as disassembly, with likely sprite data as .db, and exits"),
+            Arg::with_name("watch")
+                .long("watch")
+                .help("Reloads the rom whenever its file changes"),
+            Arg::with_name("screenshot_interval")
+                .long("screenshot-interval")
+                .value_name("MS")
+                .help("Saves a numbered png screenshot every MS milliseconds")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u64>() {
+                    Ok(ms) if ms > 0 => Ok(()),
+                    _ => Err(String::from("must be a positive number of milliseconds")),
+                }),
+            Arg::with_name("record_golden")
+                .long("record-golden")
+                .value_name("GOLDEN_PATH")
+                .help("Runs the rom without input and writes per-frame display hashes, then exits")
+                .takes_value(true)
+                .conflicts_with("verify_golden"),
+            Arg::with_name("verify_golden")
+                .long("verify-golden")
+                .value_name("GOLDEN_PATH")
+                .help("Runs the rom without input and compares per-frame display hashes, then exits")
+                .takes_value(true),
+            Arg::with_name("benchmark")
+                .long("benchmark")
+                .help("Runs the rom without input as fast as possible and prints instructions per second, then exits"),
+            Arg::with_name("golden_frames")
+                .long("golden-frames")
+                .value_name("FRAMES")
+                .help("Sets how many frames --record-golden runs")
+                .takes_value(true)
+                .default_value("600")
+                .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|_| String::from("must be a number of frames")))
+        ]);
+    #[cfg(feature = "record-audio")]
+    let app = app.arg(
+        Arg::with_name("record_audio")
+            .long("record-audio")
+            .value_name("WAV_PATH")
+            .help("Writes the tone the rom played to a wav file on exit")
+            .takes_value(true),
+    );
+    #[cfg(feature = "tui")]
+    let app = app.arg(
+        Arg::with_name("tui")
+            .long("tui")
+            .help("Runs in the terminal instead of a window, keys staying down briefly after each press"),
+    );
+    let matches = app.get_matches();
+
+    if let Some(path) = matches.value_of("annotate_trace") {
+        let records = trace::read_trace(path).unwrap_or_else(|error| {
+            eprintln!("Could not read {}: {}", path, error);
+            std::process::exit(1);
+        });
+        for line in trace::annotate(&records) { println!("{}", line); }
+        return;
+    }
+
+    if matches.is_present("self_test") {
+        let sdl = sdl2::init().expect("Could not create SDL!");
+        let mut chip = Chip8::new(&sdl);
+        let mut result = diagnostic::run(&mut chip);
+        chip.present();
+        if result.is_ok() && !chip.play_tone(SELF_TEST_BEEP) { result = Err(String::from("no audio device")); }
+        match result {
+            Ok(()) => println!("Self-test passed"),
+            Err(message) => {
+                println!("Self-test failed: {}", message);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
     let rom_path = matches.value_of("rom_path").expect("Args error!").trim();
-    let sdl = sdl2::init().expect("Could not create SDL!");
-    let mut chip = Chip8::new(&sdl);
-    
-    chip.load_rom(rom_path);
+    if matches.is_present("disassemble") {
+        let rom = fs::read(rom_path).unwrap_or_else(|error| {
+            eprintln!("Could not load {}: {}", rom_path, error);
+            std::process::exit(1);
+        });
+        for line in analysis::disassemble(&rom) { println!("{}", line); }
+        return;
+    }
+
+    let platform = matches.value_of("platform").and_then(Platform::from_name);
+    if let Some(platform) = platform {
+        if let Err(message) = platform::check_supported(platform) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+    }
+
+    // The terminal needs no window, so SDL stays uninitialised
+    let mut chip = if matches.is_present("tui") {
+        Chip8::new_headless()
+    } else {
+        Chip8::new(&sdl2::init().expect("Could not create SDL!"))
+    };
+    if let Some(platform) = platform { chip.set_platform(platform); }
+    if matches.value_of("palette") == Some("grayscale") { chip.set_palette(Palette::grayscale()); }
+    chip.set_fade(matches.is_present("fade"));
+    chip.set_debug_hud(matches.is_present("debug_hud"));
+    chip.set_ruler(matches.is_present("ruler"));
+    if matches.is_present("show_keys") { chip.set_show_keys(true); }
+    if matches.is_present("debug_panel") { chip.set_debug_panel(true); }
+    chip.set_scale(matches.value_of("scale").expect("Args error!").parse().expect("Args error!"));
+    // A window width picks its own scale, overriding --scale
+    if let Some(px) = matches.value_of("window_width") { chip.fit_window_width(px.parse().expect("Args error!")); }
+    if let Some(seed) = matches.value_of("seed") { chip.seed_rng(seed.parse().expect("Args error!")); }
+    match matches.value_of("byte_swap") {
+        Some("always") => chip.set_byte_swap(ByteSwap::Always),
+        Some("auto") => chip.set_byte_swap(ByteSwap::Auto),
+        _ => {}
+    }
+
+    if let Some(fill) = matches.value_of("power_on_fill") {
+        chip.set_power_on_fill(u8::from_str_radix(fill.trim_start_matches("0x"), 16).expect("Args error!"));
+        chip.reset();
+    }
+
+    if let Err(error) = chip.load_rom(rom_path) {
+        eprintln!("Could not load {}: {}", rom_path, error);
+        std::process::exit(1);
+    }
+    if matches.value_of("platform") == Some("auto") {
+        chip.set_quirks(platform::suggest_quirks(chip.rom_hash(), chip.rom()));
+    }
+    let mut quirks = chip.quirks();
+    quirks.shift_uses_vy |= matches.is_present("shift_quirk");
+    quirks.load_store_increments_i |= matches.is_present("load_store_quirk");
+    quirks.fx1e_overflow |= matches.is_present("fx1e_quirk");
+    chip.set_quirks(quirks);
+    if matches.is_present("watch") { chip.watch_rom(rom_path); }
+    if let Some(ms) = matches.value_of("screenshot_interval") {
+        chip.set_screenshot_interval(Duration::from_millis(ms.parse().expect("Args error!")));
+    }
+    let golden_frames = matches.value_of("golden_frames").expect("Args error!").parse().expect("Args error!");
+    if let Some(path) = matches.value_of("record_golden") {
+        let hashes = golden::record(&mut chip, &[], golden_frames);
+        golden::write_golden(path, &hashes).expect("Could not write golden file!");
+        return;
+    }
+    if let Some(path) = matches.value_of("verify_golden") {
+        let expected = golden::read_golden(path).expect("Could not read golden file!");
+        let actual = golden::record(&mut chip, &[], expected.len() as u64);
+        if let Some(frame) = golden::first_mismatch(&expected, &actual) {
+            println!("Frame {} differs from {}", frame, path);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if matches.is_present("benchmark") {
+        chip.seed_rng(golden::GOLDEN_SEED);
+        chip.run_unthrottled(BENCHMARK_FRAMES);
+        println!("{}", chip.metrics().report());
+        return;
+    }
+
+    let ips = matches.value_of("speed").expect("Args error!").parse().expect("Args error!");
+    chip.set_target_ips(ips);
+    if ips < SLOW_IPS { chip.set_debug_panel(true); }
+    if let Some(hz) = matches.value_of("timer_hz") { chip.set_timer_hz(hz.parse().expect("Args error!")); }
+    let quit_key = Keycode::from_name(matches.value_of("quit_key").expect("Args error!")).expect("Args error!");
+    if matches.is_present("two_player") { chip.set_key_map(KeyMap::two_player()); }
+    if let Some(path) = matches.value_of("keymap") {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let (key_map, warnings) = KeyMap::parse(&text, quit_key);
+                for warning in warnings { eprintln!("Ignoring {} {}", path, warning); }
+                chip.set_key_map(key_map);
+            }
+            Err(error) => eprintln!("Could not read {}, using the default keys: {}", path, error),
+        }
+    }
+    chip.set_quit_key(quit_key);
+    chip.set_wait_for_key(matches.is_present("wait_for_key"));
+    if matches.is_present("profile") { chip.enable_profiling(); }
+    if matches.is_present("record_audio") { chip.enable_audio_recording(); }
+    if matches.is_present("mute") { chip.set_muted(true); }
+    if matches.is_present("log_invalid") { chip.enable_invalid_log(); }
+    chip.set_safe_mode(matches.is_present("safe_mode"));
+    chip.set_strict_alignment(matches.is_present("strict_alignment"));
+    chip.set_legacy_timer_mode(matches.is_present("legacy_timers"));
+    chip.set_break_on_collision(matches.is_present("break_on_collision"));
+    if matches.is_present("semihosting") {
+        chip.set_semihosting(|value| println!("Semihosting: {:#04X}", value));
+    }
+    if let Some(path) = matches.value_of("trace") {
+        chip.start_trace(path).expect("Could not create trace!");
+    }
+    #[cfg(feature = "tui")]
+    if matches.is_present("tui") {
+        tui::run(&mut chip).expect("Could not run in the terminal!");
+    } else {
+        chip.start_cycle();
+    }
+    #[cfg(not(feature = "tui"))]
     chip.start_cycle();
+    if let Err(error) = chip.stop_trace() { eprintln!("Could not write trace: {}", error); }
+    if let Err(error) = chip.stop_recording() { eprintln!("Could not save recording: {}", error); }
+
+    if let Some(fault) = chip.fault() {
+        println!("Fault: {:?} at {:#05X}", fault, chip.state().pc);
+        for addr in chip.call_stack().iter().rev() { println!("  called from {:#05X}", addr); }
+        if fault == Fault::Collision {
+            if let Err(error) = chip.screenshot("collision.png") { eprintln!("Could not save collision.png: {}", error); }
+        }
+    }
+
+    if matches.is_present("log_invalid") {
+        println!("Unknown opcodes: {}", chip.invalid_op_codes().len());
+        for (addr, op_code) in chip.invalid_op_codes() { println!("{:#05X}: {:04X}", addr, op_code); }
+    }
+
+    #[cfg(feature = "record-audio")]
+    if let Some(path) = matches.value_of("record_audio") {
+        chip.export_audio(path).expect("Could not write audio!");
+    }
+
+    if let Some(path) = matches.value_of("profile") {
+        chip.export_profile(path).expect("Could not write profile!");
+    }
 }
@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+// Work done by the interpreter and the wall time it took
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    pub instructions: u64,
+    pub frames: u64,
+    pub elapsed: Duration,
+}
+
+impl Metrics {
+    pub fn instructions_per_second(&self) -> f64 {
+        if self.elapsed.is_zero() { return 0.0; }
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+
+    pub fn report(&self) -> String {
+        format!(
+            "Frames: {}\nInstructions: {}\nTime: {:.3}s\nIPS: {:.0}",
+            self.frames,
+            self.instructions,
+            self.elapsed.as_secs_f64(),
+            self.instructions_per_second(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let metrics = Metrics { instructions: 60_000, frames: 6_000, elapsed: Duration::from_millis(250) };
+        assert_eq!(metrics.report(), "Frames: 6000\nInstructions: 60000\nTime: 0.250s\nIPS: 240000");
+        assert_eq!(Metrics::default().instructions_per_second(), 0.0);
+    }
+}
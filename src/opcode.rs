@@ -0,0 +1,169 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Opcode {
+    Ret,
+    Cls,
+    ScdNibble { n: u8 },
+    Scr,
+    Scl,
+    Low,
+    High,
+    JpAddr { nnn: u16 },
+    CallAddr { nnn: u16 },
+    SeVxByte { x: u8, kk: u8 },
+    SneVxByte { x: u8, kk: u8 },
+    SeVxVy { x: u8, y: u8 },
+    LdVxByte { x: u8, kk: u8 },
+    AddVxByte { x: u8, kk: u8 },
+    LdVxVy { x: u8, y: u8 },
+    OrVxVy { x: u8, y: u8 },
+    AndVxVy { x: u8, y: u8 },
+    XorVxVy { x: u8, y: u8 },
+    AddVxVy { x: u8, y: u8 },
+    SubVxVy { x: u8, y: u8 },
+    ShrVxVy { x: u8, y: u8 },
+    SubnVxVy { x: u8, y: u8 },
+    ShlVxVy { x: u8, y: u8 },
+    SneVxVy { x: u8, y: u8 },
+    LdIAddr { nnn: u16 },
+    JpV0Addr { x: u8, nnn: u16 },
+    RndVxByte { x: u8, kk: u8 },
+    DrwVxVyNibble { x: u8, y: u8, n: u8 },
+    SkpVx { x: u8 },
+    SknpVx { x: u8 },
+    LdVxDt { x: u8 },
+    LdVxK { x: u8 },
+    LdDtVx { x: u8 },
+    LdStVx { x: u8 },
+    AddIVx { x: u8 },
+    LdFVx { x: u8 },
+    LdHfVx { x: u8 },
+    LdBVx { x: u8 },
+    LdIVx { x: u8 },
+    LdVxI { x: u8 },
+}
+
+pub fn decode(code: u16) -> Option<Opcode> {
+    let (op1, op2, op3, op4) = (
+        ((code & 0xF000) >> 12) as u8,
+        ((code & 0x0F00) >> 8) as u8,
+        ((code & 0x00F0) >> 4) as u8,
+        (code & 0x000F) as u8,
+    );
+
+    // A 12-bit value, the lowest 12 bits of the instruction
+    let nnn: u16 = code & 0xFFF;
+
+    // A 4-bit value, the lowest 4 bits of the instruction
+    let n: u8 = op4;
+
+    // A 4-bit value, the lower 4 bits of the high byte of the instruction
+    let x: u8 = op2;
+
+    // A 4-bit value, the upper 4 bits of the low byte of the instruction
+    let y: u8 = op3;
+
+    // An 8-bit value, the lowest 8 bits of the instruction
+    let kk: u8 = (code & 0xFF) as u8;
+
+    let opcode = match (op1, op2, op3, op4) {
+        (0x0, 0x0, 0xE, 0xE) => Opcode::Ret,
+        (0x0, 0x0, 0xE, 0x0) => Opcode::Cls,
+        (0x0, 0x0, 0xC, _) => Opcode::ScdNibble { n },
+        (0x0, 0x0, 0xF, 0xB) => Opcode::Scr,
+        (0x0, 0x0, 0xF, 0xC) => Opcode::Scl,
+        (0x0, 0x0, 0xF, 0xE) => Opcode::Low,
+        (0x0, 0x0, 0xF, 0xF) => Opcode::High,
+        (0x1, _, _, _) => Opcode::JpAddr { nnn },
+        (0x2, _, _, _) => Opcode::CallAddr { nnn },
+        (0x3, _, _, _) => Opcode::SeVxByte { x, kk },
+        (0x4, _, _, _) => Opcode::SneVxByte { x, kk },
+        (0x5, _, _, 0x0) => Opcode::SeVxVy { x, y },
+        (0x6, _, _, _) => Opcode::LdVxByte { x, kk },
+        (0x7, _, _, _) => Opcode::AddVxByte { x, kk },
+        (0x8, _, _, 0x0) => Opcode::LdVxVy { x, y },
+        (0x8, _, _, 0x1) => Opcode::OrVxVy { x, y },
+        (0x8, _, _, 0x2) => Opcode::AndVxVy { x, y },
+        (0x8, _, _, 0x3) => Opcode::XorVxVy { x, y },
+        (0x8, _, _, 0x4) => Opcode::AddVxVy { x, y },
+        (0x8, _, _, 0x5) => Opcode::SubVxVy { x, y },
+        (0x8, _, _, 0x6) => Opcode::ShrVxVy { x, y },
+        (0x8, _, _, 0x7) => Opcode::SubnVxVy { x, y },
+        (0x8, _, _, 0xE) => Opcode::ShlVxVy { x, y },
+        (0x9, _, _, 0x0) => Opcode::SneVxVy { x, y },
+        (0xA, _, _, _) => Opcode::LdIAddr { nnn },
+        (0xB, _, _, _) => Opcode::JpV0Addr { x, nnn },
+        (0xC, _, _, _) => Opcode::RndVxByte { x, kk },
+        (0xD, _, _, _) => Opcode::DrwVxVyNibble { x, y, n },
+        (0xE, _, 0x9, 0xE) => Opcode::SkpVx { x },
+        (0xE, _, 0xA, 0x1) => Opcode::SknpVx { x },
+        (0xF, _, 0x0, 0x7) => Opcode::LdVxDt { x },
+        (0xF, _, 0x0, 0xA) => Opcode::LdVxK { x },
+        (0xF, _, 0x1, 0x5) => Opcode::LdDtVx { x },
+        (0xF, _, 0x1, 0x8) => Opcode::LdStVx { x },
+        (0xF, _, 0x1, 0xE) => Opcode::AddIVx { x },
+        (0xF, _, 0x2, 0x9) => Opcode::LdFVx { x },
+        (0xF, _, 0x3, 0x0) => Opcode::LdHfVx { x },
+        (0xF, _, 0x3, 0x3) => Opcode::LdBVx { x },
+        (0xF, _, 0x5, 0x5) => Opcode::LdIVx { x },
+        (0xF, _, 0x6, 0x5) => Opcode::LdVxI { x },
+        _ => return None,
+    };
+    Some(opcode)
+}
+
+// Mnemonic for any 16-bit word, data words render as DB
+pub fn mnemonic(code: u16) -> String {
+    match decode(code) {
+        Some(opcode) => opcode.to_string(),
+        None => format!("DB {:#06X}", code),
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::Ret => write!(f, "RET"),
+            Opcode::Cls => write!(f, "CLS"),
+            Opcode::ScdNibble { n } => write!(f, "SCD {:#03X}", n),
+            Opcode::Scr => write!(f, "SCR"),
+            Opcode::Scl => write!(f, "SCL"),
+            Opcode::Low => write!(f, "LOW"),
+            Opcode::High => write!(f, "HIGH"),
+            Opcode::JpAddr { nnn } => write!(f, "JP {:#05X}", nnn),
+            Opcode::CallAddr { nnn } => write!(f, "CALL {:#05X}", nnn),
+            Opcode::SeVxByte { x, kk } => write!(f, "SE V{:X}, {:#04X}", x, kk),
+            Opcode::SneVxByte { x, kk } => write!(f, "SNE V{:X}, {:#04X}", x, kk),
+            Opcode::SeVxVy { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Opcode::LdVxByte { x, kk } => write!(f, "LD V{:X}, {:#04X}", x, kk),
+            Opcode::AddVxByte { x, kk } => write!(f, "ADD V{:X}, {:#04X}", x, kk),
+            Opcode::LdVxVy { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Opcode::OrVxVy { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Opcode::AndVxVy { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Opcode::XorVxVy { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Opcode::AddVxVy { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Opcode::SubVxVy { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Opcode::ShrVxVy { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Opcode::SubnVxVy { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Opcode::ShlVxVy { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Opcode::SneVxVy { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Opcode::LdIAddr { nnn } => write!(f, "LD I, {:#05X}", nnn),
+            Opcode::JpV0Addr { nnn, .. } => write!(f, "JP V0, {:#05X}", nnn),
+            Opcode::RndVxByte { x, kk } => write!(f, "RND V{:X}, {:#04X}", x, kk),
+            Opcode::DrwVxVyNibble { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {:#03X}", x, y, n),
+            Opcode::SkpVx { x } => write!(f, "SKP V{:X}", x),
+            Opcode::SknpVx { x } => write!(f, "SKNP V{:X}", x),
+            Opcode::LdVxDt { x } => write!(f, "LD V{:X}, DT", x),
+            Opcode::LdVxK { x } => write!(f, "LD V{:X}, K", x),
+            Opcode::LdDtVx { x } => write!(f, "LD DT, V{:X}", x),
+            Opcode::LdStVx { x } => write!(f, "LD ST, V{:X}", x),
+            Opcode::AddIVx { x } => write!(f, "ADD I, V{:X}", x),
+            Opcode::LdFVx { x } => write!(f, "LD F, V{:X}", x),
+            Opcode::LdHfVx { x } => write!(f, "LD HF, V{:X}", x),
+            Opcode::LdBVx { x } => write!(f, "LD B, V{:X}", x),
+            Opcode::LdIVx { x } => write!(f, "LD [I], V{:X}", x),
+            Opcode::LdVxI { x } => write!(f, "LD V{:X}, [I]", x),
+        }
+    }
+}
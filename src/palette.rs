@@ -0,0 +1,36 @@
+// Display colors, indexed by the bitmask of the planes a pixel is lit in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [(u8, u8, u8); 4],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        // The second plane and the blend use Octo's XO-CHIP colors
+        Self { colors: [(0, 0, 0), (255, 255, 255), (255, 102, 0), (102, 34, 0)] }
+    }
+}
+
+impl Palette {
+    // Brightness grows with the plane bits, for a monochrome CRT look
+    pub fn grayscale() -> Self {
+        Self { colors: [(0, 0, 0), (85, 85, 85), (170, 170, 170), (255, 255, 255)] }
+    }
+
+    pub fn color(&self, planes: u8) -> (u8, u8, u8) { self.colors[(planes & 0b11) as usize] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grayscale() {
+        let palette = Palette::grayscale();
+        assert_eq!(palette.color(0b00), (0, 0, 0));
+        assert_eq!(palette.color(0b01), (85, 85, 85));
+        assert_eq!(palette.color(0b10), (170, 170, 170));
+        assert_eq!(palette.color(0b11), (255, 255, 255));
+        assert_eq!(Palette::default().color(1), (255, 255, 255));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::quirks::Quirks;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "chip-8" => Some(Self::Chip8),
+            "schip" => Some(Self::Schip),
+            "xo-chip" => Some(Self::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Chip8 => "CHIP-8",
+            Self::Schip => "SCHIP",
+            Self::XoChip => "XO-CHIP",
+        }
+    }
+
+    // Display at power on, high resolution modes are switched on by the rom itself
+    pub fn display_size(self) -> (usize, usize) { (64, 32) }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Self::Chip8 | Self::XoChip => Quirks::default(),
+            Self::Schip => Quirks::schip(),
+        }
+    }
+}
+
+// Roms known to need particular quirks, keyed by Chip8::rom_hash
+const KNOWN_ROMS: [(u64, Quirks); 1] = [
+    // Tetris [Fran Dachille, 1991], written for CHIP-48 which clips sprites at the edges
+    (0x04EB_2109_DC29_B1AB, Quirks {
+        origin_wrap: true,
+        pixel_wrap: false,
+        collision_row_count: false,
+        display_wait: false,
+        shift_uses_vy: false,
+        load_store_increments_i: false,
+        jump_uses_vx: false,
+        bcd_reverse: false,
+        fx1e_overflow: false,
+    }),
+];
+
+pub fn lookup_quirks(table: &[(u64, Quirks)], rom_hash: u64) -> Option<Quirks> {
+    table.iter().find(|(hash, _)| *hash == rom_hash).map(|&(_, quirks)| quirks)
+}
+
+// Quirks for a rom from the known rom table, or else guessed from the instructions it uses
+pub fn suggest_quirks(rom_hash: u64, rom: &[u8]) -> Quirks {
+    lookup_quirks(&KNOWN_ROMS, rom_hash).unwrap_or_else(|| {
+        if rom.chunks_exact(2).any(|w| is_schip_only(u16::from_be_bytes([w[0], w[1]]))) {
+            Quirks::schip()
+        } else {
+            Quirks::default()
+        }
+    })
+}
+
+// 00Cn, 00FB-00FF, Fx30, Fx75 and Fx85
+fn is_schip_only(op_code: u16) -> bool {
+    matches!(op_code & 0xFFF0, 0x00C0)
+        || (0x00FB..=0x00FF).contains(&op_code)
+        || matches!(op_code & 0xF0FF, 0xF030 | 0xF075 | 0xF085)
+}
+
+// Platforms this build can run. XO-CHIP stays out until its opcodes (F000 NNNN, 5xy2, 5xy3, Fn01
+// and the audio pattern) run, before then its roms would silently run as plain CHIP-8
+pub fn capabilities() -> Vec<Platform> { vec![Platform::Chip8, Platform::Schip] }
+
+pub fn check_supported(platform: Platform) -> Result<(), String> {
+    if capabilities().contains(&platform) {
+        Ok(())
+    } else {
+        Err(format!("{} support not compiled in", platform.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_supported() {
+        assert_eq!(check_supported(Platform::Chip8), Ok(()));
+        assert_eq!(check_supported(Platform::Schip), Ok(()));
+        assert_eq!(check_supported(Platform::XoChip), Err(String::from("XO-CHIP support not compiled in")));
+        assert_eq!(Platform::from_name("xo-chip"), Some(Platform::XoChip));
+        assert_eq!(Platform::from_name("vip"), None);
+    }
+
+    #[test]
+    fn test_suggest_quirks() {
+        let quirks = Quirks { display_wait: true, ..Quirks::default() };
+        let table = [(0x1234, Quirks::schip()), (0xC8C8, quirks)];
+        assert_eq!(lookup_quirks(&table, 0xC8C8), Some(quirks));
+        assert_eq!(lookup_quirks(&table, 0xC8C9), None);
+
+        assert_eq!(suggest_quirks(KNOWN_ROMS[0].0, &[]), KNOWN_ROMS[0].1);
+        assert_eq!(suggest_quirks(0, &[0x60, 0x01, 0x00, 0xFF]), Quirks::schip());
+        assert_eq!(suggest_quirks(0, &[0x60, 0x01, 0x00, 0xE0]), Quirks::default());
+    }
+}
@@ -0,0 +1,62 @@
+// Behaviors that differ between CHIP-8 implementations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // Dxyn starting coordinates wrap around the display
+    pub origin_wrap: bool,
+
+    // Sprite pixels past the display edge wrap around instead of being clipped
+    pub pixel_wrap: bool,
+
+    // Dxyn sets VF to the number of sprite rows that collided instead of 1
+    pub collision_row_count: bool,
+
+    // Dxyn ends the frame, as the draw waits for vblank
+    pub display_wait: bool,
+
+    // 8xy6 and 8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+
+    // Fx55 and Fx65 leave I at I + x + 1 instead of unchanged
+    pub load_store_increments_i: bool,
+
+    // Bnnn jumps to xnn + Vx, x being the top nibble of the address, instead of nnn + V0
+    pub jump_uses_vx: bool,
+
+    // Fx33 stores the ones digit at I and the hundreds at I + 2
+    pub bcd_reverse: bool,
+
+    // Fx1E sets VF to 1 when I + Vx passes 0xFFF and to 0 otherwise, like the Amiga interpreter
+    pub fx1e_overflow: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            origin_wrap: true,
+            pixel_wrap: true,
+            collision_row_count: false,
+            display_wait: false,
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: false,
+            bcd_reverse: false,
+            fx1e_overflow: false,
+        }
+    }
+}
+
+impl Quirks {
+    pub fn schip() -> Self {
+        Self {
+            origin_wrap: true,
+            pixel_wrap: false,
+            collision_row_count: true,
+            display_wait: false,
+            shift_uses_vy: false,
+            load_store_increments_i: false,
+            jump_uses_vx: true,
+            bcd_reverse: false,
+            fx1e_overflow: false,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+// Emulation advances in fixed 60 Hz frames, each ticking the delay and sound timers
+pub const TIMER_HZ: u32 = 60;
+pub const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+
+// Default for the most frames run to catch up after a stall
+pub const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+// Time between instructions when running at `ips` instructions per second, kept in nanoseconds
+// so that rates like 700 aren't rounded to whole milliseconds
+pub fn step_interval(ips: u32) -> Duration { Duration::from_nanos(1_000_000_000 / ips.max(1) as u64) }
+
+// Accumulates real elapsed time and hands it out as whole fixed steps
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+    max_steps: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(step: Duration) -> Self {
+        Self { step, accumulator: Duration::ZERO, max_steps: MAX_CATCH_UP_FRAMES }
+    }
+
+    pub fn set_max_steps(&mut self, max_steps: u32) { self.max_steps = max_steps.max(1); }
+
+    // Number of fixed steps due after `elapsed` more real time, time owed beyond
+    // `max_steps` is dropped so a stall doesn't snowball into later frames
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            if steps == self.max_steps {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        steps
+    }
+
+    // Real time still to pass before the next step is due
+    pub fn remaining(&self) -> Duration { self.step.saturating_sub(self.accumulator) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advance() {
+        let mut timestep = FixedTimestep::new(FRAME_DURATION);
+
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(10)), 1);
+        assert_eq!(timestep.advance(FRAME_DURATION * 2), 2);
+    }
+
+    #[test]
+    fn test_remaining() {
+        let mut timestep = FixedTimestep::new(Duration::from_millis(10));
+        assert_eq!(timestep.remaining(), Duration::from_millis(10));
+        timestep.advance(Duration::from_millis(14));
+        assert_eq!(timestep.remaining(), Duration::from_millis(6));
+    }
+
+    #[test]
+    fn test_step_interval() {
+        assert_eq!(step_interval(700), Duration::from_nanos(1_428_571));
+        assert_eq!(step_interval(1_000_000), Duration::from_nanos(1_000));
+        assert_eq!(step_interval(2), Duration::from_millis(500));
+
+        let mut timestep = FixedTimestep::new(step_interval(700));
+        timestep.set_max_steps(u32::MAX);
+        assert_eq!(timestep.advance(Duration::from_millis(1)), 0);
+        assert_eq!(timestep.advance(Duration::from_nanos(428_571)), 1);
+        assert_eq!(timestep.advance(Duration::from_secs(1)), 700);
+    }
+
+    #[test]
+    fn test_advance_spike() {
+        let mut timestep = FixedTimestep::new(FRAME_DURATION);
+
+        // A one second stall is 60 frames behind, only the capped frames run
+        assert_eq!(timestep.advance(Duration::from_secs(1)), MAX_CATCH_UP_FRAMES);
+        assert_eq!(timestep.advance(Duration::ZERO), 0);
+        assert_eq!(timestep.advance(FRAME_DURATION), 1);
+
+        timestep.set_max_steps(2);
+        assert_eq!(timestep.advance(Duration::from_secs(3600)), 2);
+        assert_eq!(timestep.advance(Duration::ZERO), 0);
+    }
+}
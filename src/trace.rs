@@ -0,0 +1,59 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+
+use crate::opcode;
+
+// Binary trace of executed instructions, one big-endian (pc, opcode) pair per record
+pub struct TraceWriter {
+    out: BufWriter<File>,
+}
+
+impl TraceWriter {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self { out: BufWriter::new(File::create(path)?) })
+    }
+
+    pub fn record(&mut self, pc: u16, op_code: u16) -> io::Result<()> {
+        self.out.write_all(&pc.to_be_bytes())?;
+        self.out.write_all(&op_code.to_be_bytes())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> { self.out.flush() }
+}
+
+pub fn read_trace(path: &str) -> io::Result<Vec<(u16, u16)>> {
+    let data = fs::read(path)?;
+    if data.len() % 4 != 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Truncated trace record!"));
+    }
+    Ok(data.chunks_exact(4)
+        .map(|r| (u16::from_be_bytes([r[0], r[1]]), u16::from_be_bytes([r[2], r[3]])))
+        .collect())
+}
+
+pub fn annotate(trace: &[(u16, u16)]) -> Vec<String> {
+    trace.iter()
+        .map(|&(pc, op_code)| format!("{:#05X}: {:04X}  {}", pc, op_code, opcode::mnemonic(op_code)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let path = std::env::temp_dir().join("chip8_trace_round_trip.bin");
+        let path = path.to_str().unwrap();
+        let records = [(0x200, 0x6233), (0x202, 0xA2F0), (0x204, 0xD015), (0x206, 0x1206), (0x206, 0x1206)];
+
+        let mut writer = TraceWriter::create(path).unwrap();
+        for (pc, op_code) in records { writer.record(pc, op_code).unwrap(); }
+        writer.flush().unwrap();
+
+        let trace = read_trace(path).unwrap();
+        assert_eq!(trace, records);
+        assert_eq!(annotate(&trace[..2]), ["0x200: 6233  LD V2, 0x33", "0x202: A2F0  LD I, 0x2F0"]);
+        fs::remove_file(path).unwrap();
+    }
+}
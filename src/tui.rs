@@ -0,0 +1,236 @@
+// Terminal front-end, rendering the display with each character cell holding two pixels stacked
+// vertically and turning the bytes typed into key events for the headless core
+#[cfg(feature = "tui")]
+use std::io::{self, Read, Write};
+#[cfg(feature = "tui")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "tui")]
+use std::thread;
+#[cfg(feature = "tui")]
+use std::time::Instant;
+
+use sdl2::{event::Event, keyboard::{Keycode, Mod}};
+
+#[cfg(feature = "tui")]
+use crate::chip8::Chip8;
+#[cfg(feature = "tui")]
+use crate::timing::{FixedTimestep, FRAME_DURATION};
+
+// Terminals send no key releases, so a key stays down until this many frames pass without it
+// being typed again. Longer than the gap between key repeats, shorter than the delay before the
+// first repeat
+const KEY_HOLD_FRAMES: u32 = 8;
+
+pub fn half_blocks(frame: &[Vec<u8>]) -> Vec<String> {
+    frame.chunks(2)
+        .map(|rows| {
+            let bottom = rows.get(1);
+            (0..rows[0].len())
+                .map(|x| match (rows[0][x] == 1, bottom.is_some_and(|row| row[x] == 1)) {
+                    (true, true) => '█',
+                    (true, false) => '▀',
+                    (false, true) => '▄',
+                    (false, false) => ' ',
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// One line per row, # for lit pixels and . for unlit ones
+pub fn frame_to_ascii(frame: &[Vec<u8>]) -> String {
+    frame.iter()
+        .map(|row| row.iter().map(|&pixel| if pixel == 1 { '#' } else { '.' }).collect::<String>())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// The keys in bytes read from a raw terminal. Letters of either case are the same key and escape
+// sequences other than the arrow keys are skipped
+pub fn parse_keys(input: &[u8]) -> Vec<Keycode> {
+    let mut keys = Vec::new();
+    let mut bytes = input.iter().copied().peekable();
+    while let Some(byte) = bytes.next() {
+        let key = match byte {
+            0x1B if matches!(bytes.peek(), Some(b'[' | b'O')) => {
+                bytes.next();
+                // Parameters up to the final byte of the sequence
+                let last = bytes.by_ref().find(|byte| (0x40..=0x7E).contains(byte));
+                match last {
+                    Some(b'A') => Some(Keycode::Up),
+                    Some(b'B') => Some(Keycode::Down),
+                    Some(b'C') => Some(Keycode::Right),
+                    Some(b'D') => Some(Keycode::Left),
+                    _ => None,
+                }
+            }
+            b'\r' => Some(Keycode::Return),
+            0x7F => Some(Keycode::Backspace),
+            _ => Keycode::from_i32(byte.to_ascii_lowercase() as i32),
+        };
+        keys.extend(key);
+    }
+    keys
+}
+
+// Keys held down for the terminal, which only reports presses
+#[derive(Default)]
+pub struct HeldKeys {
+    keys: Vec<(Keycode, u32)>,
+}
+
+impl HeldKeys {
+    // Returns whether the key was already down, making this press a repeat
+    pub fn press(&mut self, key: Keycode) -> bool {
+        let held = self.keys.iter_mut().find(|(held, _)| *held == key);
+        let repeat = held.is_some();
+        match held {
+            Some((_, frames)) => *frames = KEY_HOLD_FRAMES,
+            None => self.keys.push((key, KEY_HOLD_FRAMES)),
+        }
+        repeat
+    }
+
+    // Counts down one frame, returning the keys released by it
+    pub fn tick(&mut self) -> Vec<Keycode> {
+        self.keys.iter_mut().for_each(|(_, frames)| *frames -= 1);
+        let released = self.keys.iter().filter(|(_, frames)| *frames == 0).map(|&(key, _)| key).collect();
+        self.keys.retain(|(_, frames)| *frames > 0);
+        released
+    }
+}
+
+pub fn key_event(key: Keycode, down: bool, repeat: bool) -> Event {
+    let (timestamp, window_id, keycode, scancode, keymod) = (0, 0, Some(key), None, Mod::NOMOD);
+    if down {
+        Event::KeyDown { timestamp, window_id, keycode, scancode, keymod, repeat }
+    } else {
+        Event::KeyUp { timestamp, window_id, keycode, scancode, keymod, repeat }
+    }
+}
+
+// Runs a headless machine in the terminal until the quit key, typed keys going through the same
+// handling as window key events
+#[cfg(feature = "tui")]
+pub fn run(chip: &mut Chip8) -> io::Result<()> {
+    let _terminal = RawTerminal::enter()?;
+    let input = read_input();
+    let mut stdout = io::stdout();
+    let mut held = HeldKeys::default();
+    let mut timestep = FixedTimestep::new(FRAME_DURATION);
+    let mut last_update = Instant::now();
+    let mut shown: Option<Vec<Vec<u8>>> = None;
+    let mut beeping = false;
+
+    loop {
+        while let Ok(bytes) = input.try_recv() {
+            for key in parse_keys(&bytes) {
+                let repeat = held.press(key);
+                if chip.handle_event(&key_event(key, true, repeat)) { return Ok(()); }
+            }
+        }
+
+        let now = Instant::now();
+        for _ in 0..timestep.advance(now - last_update) {
+            for key in held.tick() { chip.handle_event(&key_event(key, false, false)); }
+            chip.step_frame();
+        }
+        last_update = now;
+
+        if shown.as_deref() != Some(chip.frame()) {
+            let mut screen = String::from("\x1b[H");
+            for line in half_blocks(chip.frame()) { screen += &format!("{}\x1b[K\r\n", line); }
+            screen += "\x1b[J";
+            stdout.write_all(screen.as_bytes())?;
+            stdout.flush()?;
+            shown = Some(chip.frame().to_vec());
+        }
+        // The terminal bell, once per beep
+        if chip.is_beeping() && !beeping {
+            stdout.write_all(b"\x07")?;
+            stdout.flush()?;
+        }
+        beeping = chip.is_beeping();
+
+        thread::sleep(FRAME_DURATION / 4);
+    }
+}
+
+// Reads stdin on its own thread, the bytes of each read arriving together so escape sequences
+// aren't split
+#[cfg(feature = "tui")]
+fn read_input() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 64];
+        while let Ok(len @ 1..) = io::stdin().read(&mut buffer) {
+            if sender.send(buffer[..len].to_vec()).is_err() { break; }
+        }
+    });
+    receiver
+}
+
+// Raw mode on the alternate screen with the cursor hidden, restoring the terminal when dropped
+#[cfg(feature = "tui")]
+struct RawTerminal {
+    original: libc::termios,
+}
+
+#[cfg(feature = "tui")]
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        // SAFETY: termios is plain data that tcgetattr fills in
+        let mut original: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 { return Err(io::Error::last_os_error()); }
+        let mut raw = original;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 { return Err(io::Error::last_os_error()); }
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        stdout.flush()?;
+        Ok(Self { original })
+    }
+}
+
+#[cfg(feature = "tui")]
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_blocks() {
+        let frame = vec![vec![1, 1, 0, 0], vec![1, 0, 1, 0], vec![0, 1, 0, 0]];
+        assert_eq!(half_blocks(&frame), ["█▀▄ ", " ▀  "]);
+        assert_eq!(frame_to_ascii(&frame), "##..\n#.#.\n.#..");
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys(b"wQ 1"), [Keycode::W, Keycode::Q, Keycode::Space, Keycode::Num1]);
+        assert_eq!(parse_keys(b"\x1b[A\x1bOD\x1b[1;5C"), [Keycode::Up, Keycode::Left, Keycode::Right]);
+        // A lone escape is the key, a sequence it doesn't know is dropped whole
+        assert_eq!(parse_keys(b"\x1b"), [Keycode::Escape]);
+        assert_eq!(parse_keys(b"\x1b[15~x"), [Keycode::X]);
+    }
+
+    #[test]
+    fn test_held_keys() {
+        let mut held = HeldKeys::default();
+        assert!(!held.press(Keycode::W));
+        for _ in 1..KEY_HOLD_FRAMES { assert!(held.tick().is_empty()); }
+        // A repeat keeps it down for as long again
+        assert!(held.press(Keycode::W));
+        for _ in 1..KEY_HOLD_FRAMES { assert!(held.tick().is_empty()); }
+        assert_eq!(held.tick(), [Keycode::W]);
+        assert!(!held.press(Keycode::W));
+    }
+}
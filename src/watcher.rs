@@ -0,0 +1,50 @@
+use std::fs;
+use std::time::SystemTime;
+
+// Polls a file's modification time to notice when it has been rebuilt. Polling once a frame is
+// cheap and, unlike notifications on the path, still sees a file replaced by rename
+pub struct RomWatcher {
+    path: String,
+    last_modified: Option<SystemTime>,
+}
+
+impl RomWatcher {
+    pub fn new(path: &str) -> Self {
+        let mut watcher = Self { path: path.to_string(), last_modified: None };
+        watcher.poll();
+        watcher
+    }
+
+    pub fn path(&self) -> &str { &self.path }
+
+    pub fn poll(&mut self) -> bool {
+        match fs::metadata(&self.path).and_then(|meta| meta.modified()) {
+            Ok(modified) => self.changed(modified),
+            Err(_) => false,
+        }
+    }
+
+    fn changed(&mut self, modified: SystemTime) -> bool {
+        let changed = self.last_modified.is_some_and(|last| last != modified);
+        self.last_modified = Some(modified);
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_changed() {
+        let mut watcher = RomWatcher { path: String::new(), last_modified: None };
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+
+        assert!(!watcher.changed(t0));
+        assert!(!watcher.changed(t0));
+        assert!(watcher.changed(t0 + Duration::from_millis(1)));
+        assert!(!watcher.changed(t0 + Duration::from_millis(1)));
+    }
+}
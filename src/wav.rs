@@ -0,0 +1,81 @@
+// Minimal WAV encoder for 16-bit mono PCM, plus the square wave the sound timer plays
+
+pub const SAMPLE_RATE: u32 = 48_000;
+pub const TONE_FREQUENCY: u32 = 440;
+
+const AMPLITUDE: i16 = 8_000;
+const FRAMES_PER_SECOND: u32 = 60;
+
+// One frame of samples per timeline entry, the tone sounding in frames where the sound timer was running.
+// The phase carries across frames so consecutive beep frames form one continuous tone
+pub fn square_wave(timeline: &[bool], sample_rate: u32, frequency: u32) -> Vec<i16> {
+    let samples_per_frame = (sample_rate / FRAMES_PER_SECOND) as usize;
+    let mut samples = Vec::with_capacity(timeline.len() * samples_per_frame);
+    for &on in timeline {
+        for _ in 0..samples_per_frame {
+            let n = samples.len() as u64;
+            let high = (n * 2 * frequency as u64 / sample_rate as u64).is_multiple_of(2);
+            samples.push(match (on, high) {
+                (false, _) => 0,
+                (true, true) => AMPLITUDE,
+                (true, false) => -AMPLITUDE,
+            });
+        }
+    }
+    samples
+}
+
+#[cfg(feature = "record-audio")]
+pub fn encode(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+    let data_size = samples.len() as u32 * 2;
+    let mut wav = Vec::with_capacity(44 + data_size as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * 2).to_le_bytes());
+    // Block align and bits per sample
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    for sample in samples { wav.extend_from_slice(&sample.to_le_bytes()); }
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beep() {
+        let samples = square_wave(&[false, true, true, false], SAMPLE_RATE, TONE_FREQUENCY);
+        assert_eq!(samples.len(), 4 * 800);
+        assert!(samples[..800].iter().chain(&samples[2400..]).all(|&s| s == 0));
+
+        // 440Hz over two frames is 14.7 periods, so 29 sign changes
+        let beep = &samples[800..2400];
+        assert!(beep.iter().all(|&s| s != 0));
+        assert_eq!(beep.windows(2).filter(|w| w[0] != w[1]).count(), 29);
+    }
+
+    #[cfg(feature = "record-audio")]
+    #[test]
+    fn test_encode() {
+        let samples = square_wave(&[false, true, true, false], SAMPLE_RATE, TONE_FREQUENCY);
+        let wav = encode(SAMPLE_RATE, &samples);
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(wav[24..28], SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(wav[40..44], (4 * 800 * 2u32).to_le_bytes());
+        assert_eq!(wav.len(), 44 + 4 * 800 * 2);
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn test_disassemble() {
+    let path = std::env::temp_dir().join("chip8_cli_disassemble.ch8");
+    // LD V2, 0x33 / JP 0x202 / a trailing odd byte
+    fs::write(&path, [0x62, 0x33, 0x12, 0x02, 0xF0]).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-interpreter"))
+        .arg("--disassemble")
+        .arg("--rom")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "0x200: 6233  LD V2, 0x33\n0x202: 1202  JP 0x202\n0x204: F0    .db 0xF0\n",
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-interpreter"))
+        .args(["--disassemble", "--rom", "missing.ch8"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("Could not load missing.ch8"));
+}
+
+#[test]
+fn test_annotate_trace_errors() {
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-interpreter"))
+        .args(["--annotate-trace", "missing.bin"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("Could not read missing.bin"));
+
+    // Half a record
+    let path = std::env::temp_dir().join("chip8_cli_short_trace.bin");
+    fs::write(&path, [0x02, 0x00]).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_chip8-interpreter"))
+        .arg("--annotate-trace")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Truncated trace record!"));
+}
@@ -0,0 +1,47 @@
+use std::fs;
+
+use chip8_interpreter::chip8::Chip8;
+use chip8_interpreter::platform::Platform;
+use chip8_interpreter::tui;
+
+// roms/quirks_probe.ch8 tries each quirk and draws the results as five digits across the top
+// of the screen, its source is roms/quirks_probe.asm. The golden frames under tests/golden are
+// drawn from the presets' quirks, not recorded, see tests/golden/README.md
+const FRAMES: usize = 10;
+
+fn run_probe(platform: Platform) -> Chip8 {
+    let mut chip = Chip8::new_headless();
+    chip.set_platform(platform);
+    chip.load_rom("roms/quirks_probe.ch8").unwrap();
+    for _ in 0..FRAMES { chip.step_frame(); }
+    assert_eq!(chip.fault(), None);
+    chip
+}
+
+#[test]
+fn test_probe_matches_source() {
+    let rom = fs::read("roms/quirks_probe.ch8").unwrap();
+    let source = fs::read_to_string("roms/quirks_probe.asm").unwrap();
+    // "0x200: 00E0  CLS" lines, everything unlisted is zero
+    let mut assembled = vec![0; rom.len()];
+    for line in source.lines().filter(|line| line.starts_with("0x")) {
+        let addr = usize::from_str_radix(&line[2..5], 16).unwrap() - 0x200;
+        let word = u16::from_str_radix(&line[7..11], 16).unwrap();
+        assembled[addr..addr + 2].copy_from_slice(&word.to_be_bytes());
+    }
+    assert_eq!(assembled, rom);
+}
+
+#[test]
+fn test_quirks_probe() {
+    let presets = [
+        (Platform::Chip8, "chip-8", 0x0F3E_1240_5579_FB83),
+        (Platform::Schip, "schip", 0x3464_3936_0B12_5297),
+    ];
+    for (platform, name, hash) in presets {
+        let chip = run_probe(platform);
+        let golden = fs::read_to_string(format!("tests/golden/quirks_probe-{}.txt", name)).unwrap();
+        assert_eq!(tui::frame_to_ascii(chip.frame()), golden.trim_end(), "{}", name);
+        assert_eq!(chip.frame_hash(), hash, "{}", name);
+    }
+}
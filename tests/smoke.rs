@@ -0,0 +1,17 @@
+use chip8_interpreter::chip8::Chip8;
+
+#[test]
+fn test_run_rom_without_delay() {
+    let mut chip = Chip8::new_headless();
+    // LD V0, 0 / LD F, V0 / DRW V0, V0, 5 / ADD V0, 1 / JP 0x202
+    chip.load_rom_bytes(&[0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05, 0x70, 0x01, 0x12, 0x02]).unwrap();
+
+    for _ in 0..3 { chip.step(); }
+    assert_eq!(chip.fault(), None);
+    assert!(chip.frame().iter().flatten().any(|&pixel| pixel == 1));
+
+    chip.run_unthrottled(2);
+    let metrics = chip.metrics();
+    assert_eq!(metrics.frames, 2);
+    assert_eq!(chip.state().pc & 1, 0);
+}